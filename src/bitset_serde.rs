@@ -0,0 +1,38 @@
+//! Serde of a `FixedBitSet` as its length and 64 bit words,
+//! the same on every platform, for `#[serde(with = "crate::bitset_serde")]`
+use crate::snapshot::{bitset_from_words, bitset_words};
+use fixedbitset::FixedBitSet;
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+#[derive(Serialize, Deserialize)]
+struct BitSetData {
+    len: usize,
+    words: Vec<u64>,
+}
+
+pub(crate) fn serialize<S: Serializer>(
+    bits: &FixedBitSet,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    BitSetData {
+        len: bits.len(),
+        words: bitset_words(bits),
+    }
+    .serialize(serializer)
+}
+
+/// Errors if bits past the length are set
+pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<FixedBitSet, D::Error> {
+    let data = BitSetData::deserialize(deserializer)?;
+    if data.words.len() != data.len.div_ceil(64) {
+        return Err(D::Error::custom(format!(
+            "{} words for a bitset of length {}",
+            data.words.len(),
+            data.len
+        )));
+    }
+    bitset_from_words(data.len, data.words.into_iter().map(Ok), D::Error::custom)
+}
@@ -1,24 +1,59 @@
-use crate::{CompVec, EntityHandle};
+use crate::comp_vec::{PAGE_SIZE, Page};
+use crate::{CompStorage, EntityHandle, EntityHandleCounter};
+use std::sync::Arc;
+
+#[cfg(feature = "rayon")]
+mod par;
+#[cfg(feature = "rayon")]
+pub use par::*;
 
 /// Iterator for CompVec<T>
 pub struct Iter<'a, T> {
     next_entity_ind: usize,
     owners: &'a fixedbitset::FixedBitSet,
-    vec: &'a [(EntityHandle, T)],
+    /// Index of the page `page` is part of
+    page_ind: usize,
+    /// Remaining components of the current page
+    page: &'a [(EntityHandle, T)],
+    /// Pages after the current one
+    pages: &'a [Page<T>],
 }
 
 impl<'a, T> Iter<'a, T> {
-    pub(crate) fn new(vec: &'a [(EntityHandle, T)], owners: &'a fixedbitset::FixedBitSet) -> Self {
+    pub(crate) fn new(pages: &'a [Page<T>], owners: &'a fixedbitset::FixedBitSet) -> Self {
+        let (page, pages) = match pages {
+            [] => (&[][..], pages),
+            [first, rest @ ..] => (first.comps.as_slice(), rest),
+        };
         Self {
             next_entity_ind: 0,
-            vec,
             owners,
+            page_ind: 0,
+            page,
+            pages,
         }
     }
+
+    /// Number of components of the current page before `entity_index`,
+    /// which is in the current page
+    fn page_offset(&self, entity_index: usize) -> usize {
+        let end = entity_index.min(self.owners.len());
+        self.owners.count_ones(self.next_entity_ind.min(end)..end)
+    }
+
     /// Will not advance if entity_index is smaller than the last entity index
     fn advance_forward_to(&mut self, entity_index: usize) {
-        let advance_by = self.owners.count_ones(self.next_entity_ind..entity_index);
-        self.vec = &self.vec[advance_by..];
+        let target_page = entity_index / PAGE_SIZE;
+        if target_page > self.page_ind {
+            let skip = target_page - self.page_ind - 1;
+            self.page = &self.pages[skip].comps;
+            self.pages = &self.pages[skip + 1..];
+            self.page_ind = target_page;
+            self.next_entity_ind = target_page * PAGE_SIZE;
+        }
+
+        let advance_by = self.page_offset(entity_index);
+        self.page = &self.page[advance_by..];
 
         self.next_entity_ind = entity_index;
     }
@@ -34,12 +69,21 @@ impl<'a, T> Iterator for Iter<'a, T> {
     type Item = (EntityHandle, &'a T);
 
     fn next(&mut self) -> Option<Self::Item> {
-        match self.vec {
-            [] => None,
-            [(id, out), rest @ ..] => {
-                self.vec = rest;
-                self.next_entity_ind = id.index() + 1;
-                Some((*id, out))
+        loop {
+            match self.page {
+                [(id, out), rest @ ..] => {
+                    self.page = rest;
+                    self.next_entity_ind = id.index() + 1;
+                    return Some((*id, out));
+                }
+                [] => match self.pages {
+                    [] => return None,
+                    [first, rest @ ..] => {
+                        self.page = &first.comps;
+                        self.pages = rest;
+                        self.page_ind += 1;
+                    }
+                },
             }
         }
     }
@@ -49,28 +93,67 @@ impl<'a, T> Iterator for Iter<'a, T> {
 pub struct IterMut<'a, T> {
     next_entity_ind: usize,
     owners: &'a fixedbitset::FixedBitSet,
-    vec: &'a mut [(EntityHandle, T)],
+    /// Index of the page `page` is part of
+    page_ind: usize,
+    /// Remaining components of the current page
+    page: &'a mut [(EntityHandle, T)],
+    /// Change ticks of the remaining components of the current page
+    ticks: &'a mut [u64],
+    /// Pages after the current one
+    pages: &'a mut [Page<T>],
+    /// Tick the handed out components are marked as changed at
+    tick: u64,
 }
 
 impl<'a, T> IterMut<'a, T> {
     pub(crate) fn new(
-        vec: &'a mut [(EntityHandle, T)],
+        pages: &'a mut [Page<T>],
         owners: &'a fixedbitset::FixedBitSet,
+        tick: u64,
     ) -> Self {
+        let (page, ticks, pages) = match pages {
+            [] => (&mut [][..], &mut [][..], pages),
+            [first, rest @ ..] => (first.comps.as_mut_slice(), first.ticks.as_mut_slice(), rest),
+        };
         Self {
             next_entity_ind: 0,
-            vec,
             owners,
+            page_ind: 0,
+            page,
+            ticks,
+            pages,
+            tick,
         }
     }
 
+    /// Number of components of the current page before `entity_index`,
+    /// which is in the current page
+    fn page_offset(&self, entity_index: usize) -> usize {
+        let end = entity_index.min(self.owners.len());
+        self.owners.count_ones(self.next_entity_ind.min(end)..end)
+    }
+
     /// Will not advance if entity_index is smaller than the last entity index
     fn advance_forward_to(&mut self, entity_index: usize) {
-        let advance_by = self.owners.count_ones(self.next_entity_ind..entity_index);
+        let target_page = entity_index / PAGE_SIZE;
+        if target_page > self.page_ind {
+            let skip = target_page - self.page_ind - 1;
+            let pages = std::mem::take(&mut self.pages);
+            let (page, pages) = pages[skip..].split_first_mut().unwrap();
+            self.page = &mut page.comps;
+            self.ticks = &mut page.ticks;
+            self.pages = pages;
+            self.page_ind = target_page;
+            self.next_entity_ind = target_page * PAGE_SIZE;
+        }
+
+        let advance_by = self.page_offset(entity_index);
 
         // from https://users.rust-lang.org/t/how-does-vecs-iterator-return-a-mutable-reference/60235/14
-        let slice = std::mem::take(&mut self.vec);
-        self.vec = &mut slice[advance_by..];
+        let slice = std::mem::take(&mut self.page);
+        self.page = &mut slice[advance_by..];
+        let ticks = std::mem::take(&mut self.ticks);
+        self.ticks = &mut ticks[advance_by..];
 
         self.next_entity_ind = entity_index;
     }
@@ -86,20 +169,40 @@ impl<'a, T> Iterator for IterMut<'a, T> {
     type Item = (EntityHandle, &'a mut T);
 
     fn next(&mut self) -> Option<Self::Item> {
-        // from https://users.rust-lang.org/t/how-does-vecs-iterator-return-a-mutable-reference/60235/14
-        let slice = std::mem::take(&mut self.vec);
-        match slice {
-            [] => None,
-            [(id, out), rest @ ..] => {
-                self.vec = rest;
-                self.next_entity_ind = id.index() + 1;
-                Some((*id, out))
+        loop {
+            // from https://users.rust-lang.org/t/how-does-vecs-iterator-return-a-mutable-reference/60235/14
+            let slice = std::mem::take(&mut self.page);
+            match slice {
+                [(id, out), rest @ ..] => {
+                    let (tick, ticks) = std::mem::take(&mut self.ticks).split_first_mut().unwrap();
+                    *tick = self.tick;
+                    self.ticks = ticks;
+                    self.page = rest;
+                    self.next_entity_ind = id.index() + 1;
+                    return Some((*id, out));
+                }
+                [] => match std::mem::take(&mut self.pages) {
+                    [] => return None,
+                    [first, rest @ ..] => {
+                        self.page = &mut first.comps;
+                        self.ticks = &mut first.ticks;
+                        self.pages = rest;
+                        self.page_ind += 1;
+                    }
+                },
             }
         }
     }
 }
 
-/// Trait used to simplify implementation of `CompIter`
+/// Trait used to simplify implementation of `CompIter`.
+///
+/// Tuples of `CompIterer`s whose first element is a `NonOptionalCompIterer`
+/// are `CompIterer`s too, so they can be nested: `CompIter::from(((a, b), (c, d)))`
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` can't be iterated over by `CompIter`",
+    note = "`CompIter` takes the iterators of the storages, like `CompVec::iter`, `CompVec::iter_mut` or `Optional`s of them"
+)]
 pub trait CompIterer {
     type Item;
 
@@ -146,7 +249,15 @@ impl<'a, T> CompIterer for IterMut<'a, T> {
 
 /// Makes a CompVec<T> iterator return Option<&T> or Option<&mut T> instead of T or &mut T
 /// when used as part of CompIter. Also will not affect the ownership combination in `CompIter`.
-pub struct Optional<T: NonOptionalCompIterer>(T);
+pub struct Optional<T: NonOptionalCompIterer>(pub(crate) T);
+
+impl<T: NonOptionalCompIterer> Optional<T> {
+    /// Same as the `optional` methods of the iterators, for the iterers without one
+    /// like nested tuples: `Optional::new((a.iter(), b.iter()))`
+    pub fn new(iterer: T) -> Self {
+        Optional(iterer)
+    }
+}
 
 impl<T: NonOptionalCompIterer> CompIterer for Optional<T> {
     type Item = Option<T::Item>;
@@ -154,7 +265,7 @@ impl<T: NonOptionalCompIterer> CompIterer for Optional<T> {
     fn combine_owners(&self, _owners: &mut fixedbitset::FixedBitSet) {}
 
     fn comp_at(&mut self, entity_handle: EntityHandle) -> Self::Item {
-        if self.0.owners().contains(entity_handle.index()) {
+        if self.0.contains(entity_handle.index()) {
             Some(self.0.comp_at(entity_handle))
         } else {
             None
@@ -162,7 +273,149 @@ impl<T: NonOptionalCompIterer> CompIterer for Optional<T> {
     }
 }
 
-/// `CompIter` can be not only `Iter<T>` and `IterMut<T>` but also
+/// Only the components changed since a tick, made by `CompVec::iter_changed_since`
+/// and `CompVec::iter_mut_changed_since`.
+///
+/// Is an iterator on its own, and filters out the entities whose component
+/// didn't change when used as part of `CompIter`.
+pub struct Changed<I> {
+    inner: I,
+    owners: fixedbitset::FixedBitSet,
+    ones: fixedbitset::IntoOnes,
+}
+
+impl<I: NonOptionalCompIterer> Changed<I> {
+    pub(crate) fn new(inner: I, owners: fixedbitset::FixedBitSet) -> Self {
+        Self {
+            inner,
+            ones: owners.clone().into_ones(),
+            owners,
+        }
+    }
+
+    /// Make the iter optional, meaning it will not affect ownership in `CompIter`
+    /// and will return None for unchanged components.
+    pub fn optional(self) -> Optional<Self> {
+        Optional(self)
+    }
+}
+
+impl<I: NonOptionalCompIterer> Iterator for Changed<I> {
+    type Item = (EntityHandle, I::Item);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.ones
+            .next()
+            .map(|index| self.inner.comp_at_index(index))
+    }
+}
+
+impl<I: NonOptionalCompIterer> CompIterer for Changed<I> {
+    type Item = I::Item;
+
+    fn combine_owners(&self, owners: &mut fixedbitset::FixedBitSet) {
+        owners.intersect_with(&self.owners);
+    }
+
+    fn comp_at(&mut self, entity_handle: EntityHandle) -> Self::Item {
+        self.inner.comp_at(entity_handle)
+    }
+}
+
+impl<I: NonOptionalCompIterer> NonOptionalCompIterer for Changed<I> {
+    fn owners(&self) -> &fixedbitset::FixedBitSet {
+        &self.owners
+    }
+
+    fn comp_at_index(&mut self, entity_index: usize) -> (EntityHandle, Self::Item) {
+        self.inner.comp_at_index(entity_index)
+    }
+}
+
+/// Set of entities driving a `CompIter` instead of the owners of a component,
+/// so all the other iterators can be optional. Its items are `()`, the handles
+/// coming from the `EntityHandleCounter`, which is only borrowed to build it.
+/// ```
+/// # use vec_ecs::{CompVec, CompIter, Entities, EntityHandleCounter};
+/// # let mut handles = EntityHandleCounter::default();
+/// # let handle1 = handles.next_handle();
+/// # let handle2 = handles.next_handle();
+/// # let handle3 = handles.next_handle();
+/// let mut a = CompVec::<u32>::default();
+/// let mut b = CompVec::<bool>::default();
+/// a.insert(handle1, 1);
+/// b.insert(handle3, true);
+///
+/// let v: Vec<_> = CompIter::from((
+///     Entities::any_of(&handles, &[&a, &b]),
+///     a.iter().optional(),
+///     b.iter().optional(),
+/// ))
+/// .into_iter()
+/// .collect();
+/// assert_eq!(v, vec![(handle1, (), Some(&1), None), (handle3, (), None, Some(&true))]);
+/// ```
+#[derive(Clone)]
+pub struct Entities {
+    /// Generation of every entity index, copied so the `EntityHandleCounter`
+    /// isn't borrowed during the iteration
+    generations: Arc<[Option<u32>]>,
+    owners: Arc<fixedbitset::FixedBitSet>,
+}
+
+impl Entities {
+    /// All the live entities of `handles`
+    pub fn live(handles: &EntityHandleCounter) -> Self {
+        Self::with_owners(handles, handles.live_entities())
+    }
+
+    /// The entities of `owners` that are alive in `handles`
+    pub fn in_set(handles: &EntityHandleCounter, owners: &fixedbitset::FixedBitSet) -> Self {
+        let mut live = handles.live_entities();
+        live.intersect_with(owners);
+        Self::with_owners(handles, live)
+    }
+
+    /// The entities with a component in any of `storages`
+    pub fn any_of(handles: &EntityHandleCounter, storages: &[&dyn CompStorage]) -> Self {
+        let mut owners = fixedbitset::FixedBitSet::new();
+        for storage in storages {
+            owners.union_with(storage.owners());
+        }
+        Self::in_set(handles, &owners)
+    }
+
+    fn with_owners(handles: &EntityHandleCounter, owners: fixedbitset::FixedBitSet) -> Self {
+        Self {
+            generations: handles.generations().into(),
+            owners: Arc::new(owners),
+        }
+    }
+}
+
+impl CompIterer for Entities {
+    type Item = ();
+
+    fn combine_owners(&self, owners: &mut fixedbitset::FixedBitSet) {
+        owners.intersect_with(&self.owners);
+    }
+
+    fn comp_at(&mut self, _entity_handle: EntityHandle) -> Self::Item {}
+}
+
+impl NonOptionalCompIterer for Entities {
+    fn owners(&self) -> &fixedbitset::FixedBitSet {
+        &self.owners
+    }
+
+    fn comp_at_index(&mut self, entity_index: usize) -> (EntityHandle, Self::Item) {
+        let generation = self.generations[entity_index].unwrap();
+        (EntityHandle::new(entity_index, generation), ())
+    }
+}
+
+/// `CompIter` can be not only `Iter<T>` and `IterMut<T>` (or the iterators of
+/// the other storages, like `SparseIter<T>`) but also
 /// `Optional<Iter<T>>` and `Optional<IterMut<T>>`.
 /// `Optional<...>` does not have/affect ownership of the iteration
 ///
@@ -172,9 +425,23 @@ impl<T: NonOptionalCompIterer> CompIterer for Optional<T> {
 /// set of components
 ///
 /// To do this, it was chosen that the first `CompIterer` in the
-/// `CompIter::from` tuple has to be a `NonOptionalCompIterer`
+/// `CompIter::from` tuple has to be a `NonOptionalCompIterer`.
+/// To iterate with only optional components, put an `Entities` first
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` can't be the first iterator of a `CompIter`",
+    label = "the first iterator decides which entities are iterated over",
+    note = "the first iterator of a `CompIter::from` tuple can't be `optional()`, put a non-optional one first, or an `Entities` to only have optional ones"
+)]
 pub trait NonOptionalCompIterer: CompIterer {
+    /// The entities the iterer has components for. For tuples, only the ones of the
+    /// first element, the others are combined with `combine_owners`
     fn owners(&self) -> &fixedbitset::FixedBitSet;
+
+    /// Whether the iterer has components for the entity at `entity_index`
+    fn contains(&self, entity_index: usize) -> bool {
+        self.owners().contains(entity_index)
+    }
+
     fn comp_at_index(&mut self, entity_index: usize) -> (EntityHandle, Self::Item);
 }
 
@@ -222,7 +489,8 @@ impl<T> NonOptionalCompIterer for IterMut<'_, T> {
 /// assert_eq!(v, vec![(handle1, &100, &true), (handle3, &32, &false)]);
 ///  
 /// ```
-/// The first iterator cannot be an optional one.
+/// The first iterator cannot be an optional one, see `Entities` for iterating
+/// with only optional components.
 pub struct CompIter<T> {
     comps: T,
     owners: fixedbitset::FixedBitSet,
@@ -232,7 +500,7 @@ impl<T> CompIter<T> {
     /// In the set of components specified in `CompIter::from`,
     /// ignore entities that have the specified component
     #[must_use]
-    pub fn without<Y>(mut self, without: &CompVec<Y>) -> Self {
+    pub fn without(mut self, without: &impl CompStorage) -> Self {
         self.owners.difference_with(without.owners());
         self
     }
@@ -240,7 +508,7 @@ impl<T> CompIter<T> {
     /// In the set of components specified in `CompIter::from`,
     /// only include entities that also have the specified component.
     #[must_use]
-    pub fn with<Y>(mut self, with: &CompVec<Y>) -> Self {
+    pub fn with(mut self, with: &impl CompStorage) -> Self {
         self.owners.intersect_with(with.owners());
         self
     }
@@ -263,8 +531,9 @@ macro_rules! impl_iterer {
                     )*
                 ) = comps;
 
-                #[allow(unused_mut)]
                 let mut owners = comp1.owners().to_owned();
+                // for nested tuples, the owners of the other elements of the first one
+                comp1.combine_owners(&mut owners);
                 $(
                     $names.combine_owners(&mut owners);
                 )*
@@ -313,6 +582,75 @@ macro_rules! impl_iterer {
                 })
             }
         }
+
+        impl<T1: NonOptionalCompIterer, $($generics: CompIterer, )*> CompIterer for (T1, $($generics),*) {
+            type Item = (T1::Item, $($generics::Item, )*);
+
+            fn combine_owners(&self, owners: &mut fixedbitset::FixedBitSet) {
+                let (
+                    comp1,
+                    $(
+                        $names,
+                    )*
+                ) = self;
+                comp1.combine_owners(owners);
+                $(
+                    $names.combine_owners(owners);
+                )*
+            }
+
+            fn comp_at(&mut self, entity_handle: EntityHandle) -> Self::Item {
+                let (
+                    comp1,
+                    $(
+                        $names,
+                    )*
+                ) = self;
+                (
+                    comp1.comp_at(entity_handle),
+                    $(
+                        $names.comp_at(entity_handle),
+                    )*
+                )
+            }
+        }
+
+        impl<T1: NonOptionalCompIterer, $($generics: NonOptionalCompIterer, )*> NonOptionalCompIterer
+            for (T1, $($generics),*)
+        {
+            fn owners(&self) -> &fixedbitset::FixedBitSet {
+                self.0.owners()
+            }
+
+            fn contains(&self, entity_index: usize) -> bool {
+                let (
+                    comp1,
+                    $(
+                        $names,
+                    )*
+                ) = self;
+                comp1.contains(entity_index) $(&& $names.contains(entity_index))*
+            }
+
+            fn comp_at_index(&mut self, entity_index: usize) -> (EntityHandle, Self::Item) {
+                let (
+                    comp1,
+                    $(
+                        $names,
+                    )*
+                ) = self;
+                let (id1, comp1) = comp1.comp_at_index(entity_index);
+                (
+                    id1,
+                    (
+                        comp1,
+                        $(
+                            $names.comp_at(id1),
+                        )*
+                    ),
+                )
+            }
+        }
     };
 }
 impl_iterer!(;);
@@ -322,3 +660,12 @@ impl_iterer!(T2, T3, T4; comp2, comp3, comp4);
 impl_iterer!(T2, T3, T4, T5; comp2, comp3, comp4, comp5);
 impl_iterer!(T2, T3, T4, T5, T6; comp2, comp3, comp4, comp5, comp6);
 impl_iterer!(T2, T3, T4, T5, T6, T7; comp2, comp3, comp4, comp5, comp6, comp7);
+impl_iterer!(T2, T3, T4, T5, T6, T7, T8; comp2, comp3, comp4, comp5, comp6, comp7, comp8);
+impl_iterer!(T2, T3, T4, T5, T6, T7, T8, T9; comp2, comp3, comp4, comp5, comp6, comp7, comp8, comp9);
+impl_iterer!(T2, T3, T4, T5, T6, T7, T8, T9, T10; comp2, comp3, comp4, comp5, comp6, comp7, comp8, comp9, comp10);
+impl_iterer!(T2, T3, T4, T5, T6, T7, T8, T9, T10, T11; comp2, comp3, comp4, comp5, comp6, comp7, comp8, comp9, comp10, comp11);
+impl_iterer!(T2, T3, T4, T5, T6, T7, T8, T9, T10, T11, T12; comp2, comp3, comp4, comp5, comp6, comp7, comp8, comp9, comp10, comp11, comp12);
+impl_iterer!(T2, T3, T4, T5, T6, T7, T8, T9, T10, T11, T12, T13; comp2, comp3, comp4, comp5, comp6, comp7, comp8, comp9, comp10, comp11, comp12, comp13);
+impl_iterer!(T2, T3, T4, T5, T6, T7, T8, T9, T10, T11, T12, T13, T14; comp2, comp3, comp4, comp5, comp6, comp7, comp8, comp9, comp10, comp11, comp12, comp13, comp14);
+impl_iterer!(T2, T3, T4, T5, T6, T7, T8, T9, T10, T11, T12, T13, T14, T15; comp2, comp3, comp4, comp5, comp6, comp7, comp8, comp9, comp10, comp11, comp12, comp13, comp14, comp15);
+impl_iterer!(T2, T3, T4, T5, T6, T7, T8, T9, T10, T11, T12, T13, T14, T15, T16; comp2, comp3, comp4, comp5, comp6, comp7, comp8, comp9, comp10, comp11, comp12, comp13, comp14, comp15, comp16);
@@ -0,0 +1,415 @@
+use super::{CompIter, CompIterer, Entities, Iter, IterMut, NonOptionalCompIterer, Optional};
+use crate::EntityHandle;
+use crate::comp_vec::PAGE_SIZE;
+use fixedbitset::{Block, FixedBitSet};
+use rayon::iter::plumbing::{Folder, UnindexedConsumer, UnindexedProducer, bridge_unindexed};
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+const BLOCK_BITS: usize = Block::BITS as usize;
+
+/// A `CompIterer` that can be split into the components of the entities
+/// before and after an entity index, to iterate over them on different threads
+pub trait ParCompIterer: CompIterer<Item: Send> + Send + Sized {
+    /// Splits into the components of the entities before `entity_index` and the others.
+    /// `entity_index` is either in the page the iterer is at, or the start of a page after it
+    fn split_at(self, entity_index: usize) -> (Self, Self);
+}
+
+impl<T: Sync> ParCompIterer for Iter<'_, T> {
+    fn split_at(self, entity_index: usize) -> (Self, Self) {
+        let target_page = entity_index / PAGE_SIZE;
+        if target_page == self.page_ind {
+            let (left, right) = self.page.split_at(self.page_offset(entity_index));
+            return (
+                Iter {
+                    page: left,
+                    pages: &[],
+                    ..self
+                },
+                Iter {
+                    next_entity_ind: entity_index,
+                    page: right,
+                    ..self
+                },
+            );
+        }
+        debug_assert_eq!(entity_index % PAGE_SIZE, 0);
+        let skip = target_page - self.page_ind - 1;
+        let (left, right) = self.pages.split_at(skip.min(self.pages.len()));
+        let (page, pages) = match right {
+            [first, rest @ ..] => (first.comps.as_slice(), rest),
+            [] => (&[][..], right),
+        };
+        (
+            Iter {
+                pages: left,
+                ..self
+            },
+            Iter {
+                next_entity_ind: entity_index,
+                owners: self.owners,
+                page_ind: target_page,
+                page,
+                pages,
+            },
+        )
+    }
+}
+
+impl<T: Send> ParCompIterer for IterMut<'_, T> {
+    fn split_at(self, entity_index: usize) -> (Self, Self) {
+        let target_page = entity_index / PAGE_SIZE;
+        if target_page == self.page_ind {
+            let offset = self.page_offset(entity_index);
+            let (left_page, right_page) = self.page.split_at_mut(offset);
+            let (left_ticks, right_ticks) = self.ticks.split_at_mut(offset);
+            return (
+                IterMut {
+                    next_entity_ind: self.next_entity_ind,
+                    owners: self.owners,
+                    page_ind: self.page_ind,
+                    page: left_page,
+                    ticks: left_ticks,
+                    pages: &mut [],
+                    tick: self.tick,
+                },
+                IterMut {
+                    next_entity_ind: entity_index,
+                    owners: self.owners,
+                    page_ind: self.page_ind,
+                    page: right_page,
+                    ticks: right_ticks,
+                    pages: self.pages,
+                    tick: self.tick,
+                },
+            );
+        }
+        debug_assert_eq!(entity_index % PAGE_SIZE, 0);
+        let skip = target_page - self.page_ind - 1;
+        let (left, right) = self.pages.split_at_mut(skip.min(self.pages.len()));
+        let (page, ticks, pages) = match right.split_first_mut() {
+            Some((first, rest)) => (first.comps.as_mut_slice(), first.ticks.as_mut_slice(), rest),
+            None => (&mut [][..], &mut [][..], &mut [][..]),
+        };
+        (
+            IterMut {
+                next_entity_ind: self.next_entity_ind,
+                owners: self.owners,
+                page_ind: self.page_ind,
+                page: self.page,
+                ticks: self.ticks,
+                pages: left,
+                tick: self.tick,
+            },
+            IterMut {
+                next_entity_ind: entity_index,
+                owners: self.owners,
+                page_ind: target_page,
+                page,
+                ticks,
+                pages,
+                tick: self.tick,
+            },
+        )
+    }
+}
+
+impl<I: ParCompIterer + NonOptionalCompIterer> ParCompIterer for Optional<I> {
+    fn split_at(self, entity_index: usize) -> (Self, Self) {
+        let (left, right) = self.0.split_at(entity_index);
+        (Optional(left), Optional(right))
+    }
+}
+
+impl ParCompIterer for Entities {
+    fn split_at(self, _entity_index: usize) -> (Self, Self) {
+        // the owners are only looked up, both halves can share all of them
+        (self.clone(), self)
+    }
+}
+
+/// The tuples of `ParCompIterer`s that `CompIter` can iterate over in parallel
+pub trait ParCompIterers: Send + Sized {
+    type Item: Send;
+
+    fn split_at(self, entity_index: usize) -> (Self, Self);
+
+    /// Components of the entity at `entity_index`, owned by the combined owners
+    fn comps_at_index(&mut self, entity_index: usize) -> Self::Item;
+}
+
+macro_rules! impl_par_iterer {
+    ($($generics:ident),* ; $($names:ident),*) => {
+        impl<T1: ParCompIterer + NonOptionalCompIterer, $($generics: ParCompIterer, )*> ParCompIterers
+            for (T1, $($generics),*)
+        {
+            type Item = (EntityHandle, T1::Item, $($generics::Item, )*);
+
+            fn split_at(self, entity_index: usize) -> (Self, Self) {
+                let (
+                    comp1,
+                    $(
+                        $names,
+                    )*
+                ) = self;
+                let comp1 = comp1.split_at(entity_index);
+                $(
+                    let $names = $names.split_at(entity_index);
+                )*
+                ((comp1.0, $($names.0,)*), (comp1.1, $($names.1,)*))
+            }
+
+            fn comps_at_index(&mut self, entity_index: usize) -> Self::Item {
+                let (
+                    comp1,
+                    $(
+                        $names,
+                    )*
+                ) = self;
+                let (id1, comp1) = comp1.comp_at_index(entity_index);
+                (
+                    id1,
+                    comp1,
+                    $(
+                        $names.comp_at(id1),
+                    )*
+                )
+            }
+        }
+
+        impl<T1: ParCompIterer + NonOptionalCompIterer, $($generics: ParCompIterer, )*> ParCompIterer
+            for (T1, $($generics),*)
+        {
+            fn split_at(self, entity_index: usize) -> (Self, Self) {
+                ParCompIterers::split_at(self, entity_index)
+            }
+        }
+    };
+}
+impl_par_iterer!(;);
+impl_par_iterer!(T2; comp2);
+impl_par_iterer!(T2, T3; comp2, comp3);
+impl_par_iterer!(T2, T3, T4; comp2, comp3, comp4);
+impl_par_iterer!(T2, T3, T4, T5; comp2, comp3, comp4, comp5);
+impl_par_iterer!(T2, T3, T4, T5, T6; comp2, comp3, comp4, comp5, comp6);
+impl_par_iterer!(T2, T3, T4, T5, T6, T7; comp2, comp3, comp4, comp5, comp6, comp7);
+impl_par_iterer!(T2, T3, T4, T5, T6, T7, T8; comp2, comp3, comp4, comp5, comp6, comp7, comp8);
+impl_par_iterer!(T2, T3, T4, T5, T6, T7, T8, T9; comp2, comp3, comp4, comp5, comp6, comp7, comp8, comp9);
+impl_par_iterer!(T2, T3, T4, T5, T6, T7, T8, T9, T10; comp2, comp3, comp4, comp5, comp6, comp7, comp8, comp9, comp10);
+impl_par_iterer!(T2, T3, T4, T5, T6, T7, T8, T9, T10, T11; comp2, comp3, comp4, comp5, comp6, comp7, comp8, comp9, comp10, comp11);
+impl_par_iterer!(T2, T3, T4, T5, T6, T7, T8, T9, T10, T11, T12; comp2, comp3, comp4, comp5, comp6, comp7, comp8, comp9, comp10, comp11, comp12);
+impl_par_iterer!(T2, T3, T4, T5, T6, T7, T8, T9, T10, T11, T12, T13; comp2, comp3, comp4, comp5, comp6, comp7, comp8, comp9, comp10, comp11, comp12, comp13);
+impl_par_iterer!(T2, T3, T4, T5, T6, T7, T8, T9, T10, T11, T12, T13, T14; comp2, comp3, comp4, comp5, comp6, comp7, comp8, comp9, comp10, comp11, comp12, comp13, comp14);
+impl_par_iterer!(T2, T3, T4, T5, T6, T7, T8, T9, T10, T11, T12, T13, T14, T15; comp2, comp3, comp4, comp5, comp6, comp7, comp8, comp9, comp10, comp11, comp12, comp13, comp14, comp15);
+impl_par_iterer!(T2, T3, T4, T5, T6, T7, T8, T9, T10, T11, T12, T13, T14, T15, T16; comp2, comp3, comp4, comp5, comp6, comp7, comp8, comp9, comp10, comp11, comp12, comp13, comp14, comp15, comp16);
+
+impl<C: ParCompIterers> CompIter<C> {
+    /// Parallel iterator over the components, see `ParCompIter`
+    pub fn par_iter(self) -> ParCompIter<C> {
+        ParCompIter {
+            comps: self.comps,
+            owners: self.owners,
+        }
+    }
+}
+
+impl<C: ParCompIterers> IntoParallelIterator for CompIter<C> {
+    type Iter = ParCompIter<C>;
+    type Item = C::Item;
+
+    fn into_par_iter(self) -> ParCompIter<C> {
+        self.par_iter()
+    }
+}
+
+/// Parallel iterator made by `CompIter::par_iter`.
+///
+/// The combined owners are split into ranges of entity indices, whole pages
+/// while a range spans several of them, and every range gets its own part
+/// of every iterer: disjoint slices of the `IterMut`s, shared slices of the `Iter`s
+pub struct ParCompIter<C> {
+    comps: C,
+    owners: FixedBitSet,
+}
+
+impl<C: ParCompIterers> ParallelIterator for ParCompIter<C> {
+    type Item = C::Item;
+
+    fn drive_unindexed<Co: UnindexedConsumer<C::Item>>(self, consumer: Co) -> Co::Result {
+        let producer = CompProducer {
+            comps: self.comps,
+            owners: &self.owners,
+            start: 0,
+            end: self.owners.len(),
+        };
+        bridge_unindexed(producer, consumer)
+    }
+}
+
+/// The components of the entities in `start..end`
+struct CompProducer<'a, C> {
+    comps: C,
+    owners: &'a FixedBitSet,
+    /// Multiple of the block size
+    start: usize,
+    end: usize,
+}
+
+impl<C: ParCompIterers> UnindexedProducer for CompProducer<'_, C> {
+    type Item = C::Item;
+
+    fn split(self) -> (Self, Option<Self>) {
+        let len = self.end - self.start;
+        let mid = if len > PAGE_SIZE {
+            // ranges spanning several pages are split between pages,
+            // so the iterers can split their pages
+            self.start + len.div_ceil(PAGE_SIZE) / 2 * PAGE_SIZE
+        } else {
+            self.start + len / 2 / BLOCK_BITS * BLOCK_BITS
+        };
+        if mid == self.start {
+            return (self, None);
+        }
+        let (left, right) = self.comps.split_at(mid);
+        (
+            CompProducer {
+                comps: left,
+                owners: self.owners,
+                start: self.start,
+                end: mid,
+            },
+            Some(CompProducer {
+                comps: right,
+                owners: self.owners,
+                start: mid,
+                end: self.end,
+            }),
+        )
+    }
+
+    fn fold_with<F: Folder<C::Item>>(mut self, folder: F) -> F {
+        let ones = ones_in(self.owners, self.start, self.end);
+        folder.consume_iter(ones.map(|index| self.comps.comps_at_index(index)))
+    }
+}
+
+/// The set bits of `owners` in `start..end`, `start` being a multiple of the block size
+fn ones_in(owners: &FixedBitSet, start: usize, end: usize) -> impl Iterator<Item = usize> + '_ {
+    owners.as_slice()[start / BLOCK_BITS..end.div_ceil(BLOCK_BITS)]
+        .iter()
+        .enumerate()
+        .flat_map(move |(i, block)| {
+            let offset = start + i * BLOCK_BITS;
+            let mut block = *block;
+            std::iter::from_fn(move || {
+                (block != 0).then(|| {
+                    let bit = block.trailing_zeros() as usize;
+                    block &= block - 1;
+                    offset + bit
+                })
+            })
+        })
+        .take_while(move |index| *index < end)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{CompIter, CompStorage, CompVec, Entities, EntityHandleCounter};
+    use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+    /// Entities with every third one missing a position, every fifth one a velocity,
+    /// and every seventh one having a mass
+    fn comps(count: usize) -> (CompVec<usize>, CompVec<usize>, CompVec<usize>) {
+        let mut handles = EntityHandleCounter::default();
+        let mut pos = CompVec::default();
+        let mut vel = CompVec::default();
+        let mut mass = CompVec::default();
+        for i in 0..count {
+            let id = handles.next_handle();
+            if i % 3 != 0 {
+                pos.insert(id, 0);
+            }
+            if i % 5 != 0 {
+                vel.insert(id, i);
+            }
+            if i % 7 == 0 {
+                mass.insert(id, 2);
+            }
+        }
+        (pos, vel, mass)
+    }
+
+    #[test]
+    fn test_par_iter() {
+        // enough threads for the ranges to be split
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(8)
+            .build()
+            .unwrap();
+        // a single page, then many pages
+        for count in [200, 5000] {
+            let (mut pos, vel, mass) = comps(count);
+            pool.install(|| {
+                CompIter::from((pos.iter_mut(), vel.iter(), mass.iter().optional()))
+                    .par_iter()
+                    .for_each(|(_id, pos, vel, mass)| *pos += vel * mass.unwrap_or(&1))
+            });
+            for (id, pos) in pos.iter() {
+                let i = id.index();
+                let expected = match (i % 5, i % 7) {
+                    (0, _) => 0,
+                    (_, 0) => 2 * i,
+                    _ => i,
+                };
+                assert_eq!(*pos, expected);
+            }
+
+            let sequential: Vec<_> = CompIter::from((vel.iter(), pos.iter()))
+                .into_iter()
+                .collect();
+            let parallel: Vec<_> = pool.install(|| {
+                CompIter::from((vel.iter(), pos.iter()))
+                    .without(&mass)
+                    .into_par_iter()
+                    .collect()
+            });
+            assert_eq!(
+                sequential
+                    .into_iter()
+                    .filter(|(id, ..)| id.index() % 7 != 0)
+                    .collect::<Vec<_>>(),
+                parallel
+            );
+        }
+    }
+
+    #[test]
+    fn test_par_entities() {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(8)
+            .build()
+            .unwrap();
+        let mut handles = EntityHandleCounter::default();
+        let mut a = CompVec::default();
+        let mut b = CompVec::default();
+        for i in 0..5000 {
+            let id = handles.next_handle();
+            match i % 3 {
+                0 => a.insert(id, i),
+                1 => b.insert(id, i),
+                _ => None,
+            };
+        }
+        let entities = Entities::any_of(&handles, &[&a as &dyn CompStorage, &b]);
+        let sum: usize = pool.install(|| {
+            CompIter::from((entities, a.iter().optional(), b.iter().optional()))
+                .into_par_iter()
+                .map(|(id, (), a, b)| {
+                    assert_eq!(a.or(b), Some(&id.index()));
+                    1
+                })
+                .sum()
+        });
+        assert_eq!(sum, 3334);
+    }
+}
@@ -1,73 +1,362 @@
-use crate::EntityHandle;
+use crate::comp_iter::Changed;
+use crate::rank::RankIndex;
+use crate::{CompStorage, ComponentHooks, EntityHandle};
 use fixedbitset::FixedBitSet;
+use std::hash::{Hash, Hasher};
 
-#[derive(Debug)]
+mod entry;
+#[cfg(feature = "serde")]
+mod serde_impl;
+
+pub use entry::*;
+
+/// Number of entity indices covered by one page of a `CompVec`
+pub(crate) const PAGE_SIZE: usize = 256;
+
+/// A page holds the components of the entities with index in
+/// `page_index * PAGE_SIZE..(page_index + 1) * PAGE_SIZE`, sorted by index
+#[derive(Debug, Clone)]
+pub(crate) struct Page<T> {
+    pub(crate) comps: Vec<(EntityHandle, T)>,
+    /// Tick of the last change of every component
+    pub(crate) ticks: Vec<u64>,
+}
+
+impl<T> Default for Page<T> {
+    fn default() -> Self {
+        Self {
+            comps: Default::default(),
+            ticks: Default::default(),
+        }
+    }
+}
+
+impl<T> Page<T> {
+    /// Mutable access to a component, marking it as changed at `tick`
+    pub(crate) fn get_mut(&mut self, comp_ind: usize, tick: u64) -> &mut (EntityHandle, T) {
+        self.ticks[comp_ind] = tick;
+        &mut self.comps[comp_ind]
+    }
+
+    pub(crate) fn insert(&mut self, comp_ind: usize, comp: (EntityHandle, T), tick: u64) {
+        self.comps.insert(comp_ind, comp);
+        self.ticks.insert(comp_ind, tick);
+    }
+
+    pub(crate) fn push(&mut self, comp: (EntityHandle, T), tick: u64) {
+        self.comps.push(comp);
+        self.ticks.push(tick);
+    }
+
+    pub(crate) fn remove(&mut self, comp_ind: usize) -> (EntityHandle, T) {
+        self.ticks.remove(comp_ind);
+        self.comps.remove(comp_ind)
+    }
+}
+
+#[derive(Debug, Clone)]
 /// A vector of components similar to `Vec<Option<(EntityHandle, T)>>`,
 /// but using a bitset instead of options to track presence of elements,
 /// Making the underlying storage just a `Vec<(EntityHandle, T)>`
+/// split into pages of `PAGE_SIZE` entity indices.
+///
+/// The position of an element in its page is the number of owners before
+/// it in the page, so get, insert, remove, etc. only count the bitset over
+/// a single page, and insert/remove only shift the rest of that page.
 ///
-/// All methods such as get, remove, etc. are technically O(n)
-/// since the bitset needs to be counted up to a point to figure out
-/// the index of the element. It's kinda like O(n/bit_size) though where
-/// bit_size is is number of bits used as the base type in the bit set
+/// Every component also has the tick it was last changed at. The tick of the
+/// `CompVec` goes up every time mutable references are handed out (`get_mut`,
+/// `iter_mut`, ...) or components are inserted, see `iter_changed_since`.
+/// Values are not compared: handing out a `&mut` marks the component as changed
+/// even if it is never written, so only take mutable access to components that are.
 ///
-/// Using a hierarchical bitset might make accesses faster for
-/// large number of components
+/// Added and removed components can also be recorded, see `set_tracking`,
+/// or trigger callbacks, see `set_hooks`.
+///
+/// Any change also sets a dirty flag, see `is_dirty`.
+///
+/// Two `CompVec`s are equal if the same entities have equal components,
+/// the change ticks, tracking and hooks are not compared.
 pub struct CompVec<T> {
-    comps: Vec<(EntityHandle, T)>,
+    pages: Vec<Page<T>>,
     owners: FixedBitSet,
+    /// Number of components in every page
+    rank: RankIndex,
+    /// Last tick components were marked as changed at
+    tick: u64,
+    /// Whether components were changed, inserted or removed since `clear_dirty`
+    dirty: bool,
+    tracking: Tracking,
+    added: Vec<EntityHandle>,
+    removed: Vec<(EntityHandle, Option<T>)>,
+    hooks: ComponentHooks<T>,
+}
+
+/// What a `CompVec` records about added and removed components,
+/// see `CompVec::drain_added` and `CompVec::drain_removed`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Tracking {
+    /// Nothing is recorded
+    #[default]
+    Off,
+    /// The handles of added and removed components are recorded
+    Handles,
+    /// Same as `Handles`, but the components that would otherwise be dropped
+    /// (by `retain` or `CompStorage::remove_entity`, used by `WorldTrait::delete_entity`)
+    /// are also kept until drained
+    Values,
 }
 
 impl<T> Default for CompVec<T> {
     fn default() -> Self {
         Self {
-            comps: Default::default(),
+            pages: Default::default(),
             owners: Default::default(),
+            rank: Default::default(),
+            tick: 0,
+            dirty: false,
+            tracking: Tracking::Off,
+            added: Default::default(),
+            removed: Default::default(),
+            hooks: Default::default(),
         }
     }
 }
 
-impl<T> CompVec<T> {
-    pub fn get(&self, id: EntityHandle) -> Option<&T> {
-        if self.owners.contains(id.index()) {
-            let comp_ind = self.owners.count_ones(0..id.index()); // exclude self
-            let (id_out, comp) = &self.comps[comp_ind];
-            if *id_out == id {
-                return Some(comp);
+/// Returns mutable references to two different elements of a slice
+fn pair_mut<X>(slice: &mut [X], ind1: usize, ind2: usize) -> (&mut X, &mut X) {
+    let max = ind1.max(ind2);
+    let (slice_min, slice_max) = slice.split_at_mut(max);
+    if ind1 < ind2 {
+        (&mut slice_min[ind1], &mut slice_max[0])
+    } else {
+        (&mut slice_max[0], &mut slice_min[ind2])
+    }
+}
+
+/// Error returned by `CompVec::get_many_mut`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GetManyError {
+    /// The entity index was requested more than once
+    DuplicateHandle(EntityHandle),
+    /// A handle couldn't be looked up, like in `try_get_mut`
+    Comp(CompVecError),
+}
+
+impl std::fmt::Display for GetManyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GetManyError::DuplicateHandle(handle) => {
+                write!(
+                    f,
+                    "entity index {} requested more than once",
+                    handle.index()
+                )
             }
+            GetManyError::Comp(e) => e.fmt(f),
         }
-        None
     }
+}
 
-    pub fn get_mut(&mut self, id: EntityHandle) -> Option<&mut T> {
-        if self.owners.contains(id.index()) {
-            let comp_ind = self.owners.count_ones(0..id.index()); // exclude self
-            let (id_out, comp) = &mut self.comps[comp_ind];
-            if *id_out == id {
-                return Some(comp);
+impl std::error::Error for GetManyError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            GetManyError::DuplicateHandle(_) => None,
+            GetManyError::Comp(e) => Some(e),
+        }
+    }
+}
+
+/// Error returned by the fallible `CompVec` operations (`try_get`, `try_insert`, ...)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompVecError {
+    /// The entity index has a component, but of another generation
+    StaleGeneration {
+        stored: EntityHandle,
+        requested: EntityHandle,
+    },
+    /// The component index is not smaller than the number of components
+    OutOfBounds { index: usize, len: usize },
+}
+
+impl std::fmt::Display for CompVecError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CompVecError::StaleGeneration { stored, requested } => write!(
+                f,
+                "stale handle {requested:?}, the component belongs to {stored:?}"
+            ),
+            CompVecError::OutOfBounds { index, len } => {
+                write!(
+                    f,
+                    "component index {index} out of bounds, there are {len} components"
+                )
             }
         }
-        None
+    }
+}
+
+impl std::error::Error for CompVecError {}
+
+impl<T> CompVec<T> {
+    /// Makes an empty `CompVec` recording added and removed components
+    pub fn with_tracking(tracking: Tracking) -> Self {
+        Self {
+            tracking,
+            ..Default::default()
+        }
+    }
+
+    /// Makes an empty `CompVec` running the hooks
+    pub fn with_hooks(hooks: ComponentHooks<T>) -> Self {
+        Self {
+            hooks,
+            ..Default::default()
+        }
+    }
+
+    /// Replaces the callbacks run when components are inserted, replaced or removed
+    pub fn set_hooks(&mut self, hooks: ComponentHooks<T>) {
+        self.hooks = hooks;
+    }
+
+    pub fn hooks(&self) -> &ComponentHooks<T> {
+        &self.hooks
+    }
+
+    /// Changes what is recorded about added and removed components.
+    /// Turning tracking off clears what was recorded
+    pub fn set_tracking(&mut self, tracking: Tracking) {
+        self.tracking = tracking;
+        if tracking == Tracking::Off {
+            self.added.clear();
+            self.removed.clear();
+        }
+    }
+
+    pub fn tracking(&self) -> Tracking {
+        self.tracking
+    }
+
+    /// The entities that had the component added (inserted while absent) since
+    /// the last call, in order of insertion. Empty if tracking is off
+    pub fn drain_added(&mut self) -> std::vec::Drain<'_, EntityHandle> {
+        self.added.drain(..)
+    }
+
+    /// The entities that had the component removed since the last call, in order
+    /// of removal. The component is only there with `Tracking::Values`, and if
+    /// it wasn't returned by the removing method
+    pub fn drain_removed(&mut self) -> std::vec::Drain<'_, (EntityHandle, Option<T>)> {
+        self.removed.drain(..)
+    }
+
+    fn record_added(&mut self, id: EntityHandle) {
+        if self.tracking != Tracking::Off {
+            self.added.push(id);
+        }
+    }
+
+    /// `comp` is the component if it would otherwise be dropped
+    fn record_removed(&mut self, id: EntityHandle, comp: Option<T>) {
+        match self.tracking {
+            Tracking::Off => {}
+            Tracking::Handles => self.removed.push((id, None)),
+            Tracking::Values => self.removed.push((id, comp)),
+        }
+    }
+
+    /// Advances the tick, for marking components as changed
+    fn next_tick(&mut self) -> u64 {
+        self.dirty = true;
+        self.tick += 1;
+        self.tick
+    }
+
+    /// Page of the entity index and position in the page its component has or would have
+    fn locate(&self, index: usize) -> (usize, usize) {
+        let page = index / PAGE_SIZE;
+        // the bitset might not have grown up to the index yet
+        let end = index.min(self.owners.len());
+        let start = (page * PAGE_SIZE).min(end);
+        let comp_ind = self.owners.count_ones(start..end); // exclude self
+        (page, comp_ind)
+    }
+
+    /// Position of the component of the entity, if it has one
+    fn try_locate(&self, id: EntityHandle) -> Result<Option<(usize, usize)>, CompVecError> {
+        if !self.owners.contains(id.index()) {
+            return Ok(None);
+        }
+        let (page, comp_ind) = self.locate(id.index());
+        let stored = self.pages[page].comps[comp_ind].0;
+        if stored == id {
+            Ok(Some((page, comp_ind)))
+        } else {
+            Err(CompVecError::StaleGeneration {
+                stored,
+                requested: id,
+            })
+        }
+    }
+
+    /// Errors if the index of the entity has a component from another generation
+    pub fn try_get(&self, id: EntityHandle) -> Result<Option<&T>, CompVecError> {
+        Ok(self
+            .try_locate(id)?
+            .map(|(page, comp_ind)| &self.pages[page].comps[comp_ind].1))
     }
 
+    /// Errors if the index of the entity has a component from another generation
+    pub fn try_get_mut(&mut self, id: EntityHandle) -> Result<Option<&mut T>, CompVecError> {
+        let Some((page, comp_ind)) = self.try_locate(id)? else {
+            return Ok(None);
+        };
+        let tick = self.next_tick();
+        Ok(Some(&mut self.pages[page].get_mut(comp_ind, tick).1))
+    }
+
+    /// Same as `try_get`, with stale handles returning None
+    pub fn get(&self, id: EntityHandle) -> Option<&T> {
+        self.try_get(id).ok().flatten()
+    }
+
+    /// Same as `try_get_mut`, with stale handles returning None
+    pub fn get_mut(&mut self, id: EntityHandle) -> Option<&mut T> {
+        self.try_get_mut(id).ok().flatten()
+    }
+
+    /// Panics if both handles have the same index, see `get_many_mut`
+    /// for a non-panicking version for any number of handles
     pub fn get2_mut(
         &mut self,
         id1: EntityHandle,
         id2: EntityHandle,
     ) -> (Option<&mut T>, Option<&mut T>) {
+        assert_ne!(
+            id1.index(),
+            id2.index(),
+            "get2_mut called with the same entity index twice"
+        );
         match (
             self.owners.contains(id1.index()),
             self.owners.contains(id2.index()),
         ) {
             (true, true) => {
-                let comp_ind1 = self.owners.count_ones(0..id1.index());
-                let comp_ind2 = self.owners.count_ones(0..id2.index());
-                let max = comp_ind1.max(comp_ind2);
-                let (slice_min, slice_max) = self.comps.split_at_mut(max);
-                let (comp1, comp2) = if comp_ind1 < comp_ind2 {
-                    (&mut slice_min[comp_ind1], &mut slice_max[0])
+                let (page1, comp_ind1) = self.locate(id1.index());
+                let (page2, comp_ind2) = self.locate(id2.index());
+                let tick = self.next_tick();
+                for (id, page, comp_ind) in [(id1, page1, comp_ind1), (id2, page2, comp_ind2)] {
+                    let page = &mut self.pages[page];
+                    if page.comps[comp_ind].0 == id {
+                        page.ticks[comp_ind] = tick;
+                    }
+                }
+                let (comp1, comp2) = if page1 == page2 {
+                    pair_mut(&mut self.pages[page1].comps, comp_ind1, comp_ind2)
                 } else {
-                    (&mut slice_max[0], &mut slice_min[comp_ind2])
+                    let (page1, page2) = pair_mut(&mut self.pages, page1, page2);
+                    (&mut page1.comps[comp_ind1], &mut page2.comps[comp_ind2])
                 };
                 (
                     (comp1.0 == id1).then_some(&mut comp1.1),
@@ -80,78 +369,462 @@ impl<T> CompVec<T> {
         }
     }
 
-    /// Returns the previous element if it was there
+    /// Mutable references to the components of several entities at once,
+    /// `None` for the entities without the component.
+    ///
+    /// Errors if two handles have the same index, or if a handle
+    /// is stale, meaning its index has a component of another generation
+    pub fn get_many_mut<const N: usize>(
+        &mut self,
+        ids: [EntityHandle; N],
+    ) -> Result<[Option<&mut T>; N], GetManyError> {
+        let mut locations = [None; N];
+        for (i, id) in ids.iter().enumerate() {
+            if ids[..i].iter().any(|other| other.index() == id.index()) {
+                return Err(GetManyError::DuplicateHandle(*id));
+            }
+            locations[i] = self.try_locate(*id).map_err(GetManyError::Comp)?;
+        }
+
+        let tick = self.next_tick();
+        for (page, comp_ind) in locations.iter().flatten() {
+            self.pages[*page].ticks[*comp_ind] = tick;
+        }
+
+        let mut order: Vec<_> = (0..N).filter(|i| locations[*i].is_some()).collect();
+        order.sort_unstable_by_key(|i| ids[*i].index());
+
+        // split the pages and the components in them in order of entity index,
+        // so every reference comes from a different part of the storage
+        let mut comps: [Option<&mut T>; N] = std::array::from_fn(|_| None);
+        let mut pages = self.pages.as_mut_slice();
+        let mut next_page = 0;
+        let mut page: &mut [(EntityHandle, T)] = &mut [];
+        let mut next_comp_ind = 0;
+        for i in order {
+            let (page_ind, comp_ind) = locations[i].unwrap();
+            if page_ind >= next_page {
+                let (first, rest) = std::mem::take(&mut pages)[page_ind - next_page..]
+                    .split_first_mut()
+                    .unwrap();
+                pages = rest;
+                next_page = page_ind + 1;
+                page = &mut first.comps;
+                next_comp_ind = 0;
+            }
+            let (comp, rest) = std::mem::take(&mut page)[comp_ind - next_comp_ind..]
+                .split_first_mut()
+                .unwrap();
+            page = rest;
+            next_comp_ind = comp_ind + 1;
+            comps[i] = Some(&mut comp.1);
+        }
+        Ok(comps)
+    }
+
+    /// Returns the previous element if it was there.
+    ///
+    /// Errors if the index of the entity has a component from another generation,
+    /// `comp` is dropped in that case
+    pub fn try_insert(&mut self, id: EntityHandle, comp: T) -> Result<Option<T>, CompVecError> {
+        Ok(match self.try_entry(id)? {
+            Entry::Occupied(mut entry) => Some(entry.insert(comp)),
+            Entry::Vacant(entry) => {
+                entry.insert(comp);
+                None
+            }
+        })
+    }
+
+    /// Panicking version of `try_insert`
     pub fn insert(&mut self, id: EntityHandle, comp: T) -> Option<T> {
-        let already_had = self.owners.contains(id.index());
+        self.try_insert(id, comp).unwrap_or_else(|e| panic!("{e}"))
+    }
 
-        self.owners.grow_and_insert(id.index());
+    /// Inserts a batch of components, returns the previous elements of the
+    /// entities that already had one, in order of entity index.
+    ///
+    /// The batch is sorted by entity index and merged into every page it touches
+    /// in one pass, which is O(n + m) instead of the O(n * m) of calling `insert`
+    /// for every component.
+    ///
+    /// Panics without inserting anything if a handle is stale, or if the batch has
+    /// handles of the same entity index with different generations.
+    ///
+    /// If an entity is in the batch more than once, the last one is kept and the
+    /// earlier ones are handled as if inserted one after the other: they are
+    /// returned with the replaced elements, and the hooks see an `on_insert`
+    /// followed by an `on_replace` for each of them.
+    pub fn insert_many(
+        &mut self,
+        comps: impl IntoIterator<Item = (EntityHandle, T)>,
+    ) -> Vec<(EntityHandle, T)> {
+        let mut batch: Vec<_> = comps.into_iter().collect();
+        batch.sort_by_key(|(id, _)| id.index());
 
-        let comp_ind = self.owners.count_ones(0..id.index()); // exclude self
+        // checked before taking any page, so a panic leaves the `CompVec` untouched
+        for (id, _) in batch.iter() {
+            if let Err(e) = self.try_locate(*id) {
+                panic!("{e}");
+            }
+        }
+        for pair in batch.windows(2) {
+            let (stored, requested) = (pair[0].0, pair[1].0);
+            if stored.index() == requested.index() && stored != requested {
+                panic!("{}", CompVecError::StaleGeneration { stored, requested });
+            }
+        }
 
-        if already_had {
-            let (old_id, comp) = std::mem::replace(&mut self.comps[comp_ind], (id, comp));
-            assert_eq!(old_id, id);
-            Some(comp)
-        } else {
-            self.comps.insert(comp_ind, (id, comp));
-            None
+        let mut replaced = Vec::new();
+        let Some((last, _)) = batch.last() else {
+            return replaced;
+        };
+        self.owners.grow(last.index() + 1);
+        let last_page = last.index() / PAGE_SIZE;
+        if last_page >= self.pages.len() {
+            self.pages.resize_with(last_page + 1, Page::default);
+        }
+        let tick = self.next_tick();
+
+        let mut batch = batch.into_iter().peekable();
+        while let Some((first, _)) = batch.peek() {
+            let page = first.index() / PAGE_SIZE;
+            let page_batch =
+                std::iter::from_fn(|| batch.next_if(|(id, _)| id.index() / PAGE_SIZE == page));
+
+            let old = std::mem::take(&mut self.pages[page]);
+            let mut merged = Page::default();
+            let mut old = old.comps.into_iter().zip(old.ticks).peekable();
+            let mut added = 0;
+            for (id, comp) in page_batch {
+                while let Some((old_comp, old_tick)) =
+                    old.next_if(|((old_id, _), _)| old_id.index() < id.index())
+                {
+                    merged.push(old_comp, old_tick);
+                }
+                // either the component already in the page, or an earlier one in the batch
+                let previous = match old.next_if(|((old_id, _), _)| old_id.index() == id.index()) {
+                    Some((previous, _)) => Some(previous),
+                    None => merged
+                        .comps
+                        .pop_if(|(prev_id, _)| prev_id.index() == id.index())
+                        .inspect(|_| {
+                            merged.ticks.pop();
+                        }),
+                };
+                match previous {
+                    Some((old_id, old_comp)) => {
+                        debug_assert_eq!(old_id, id);
+                        self.hooks.replaced(old_id, &old_comp);
+                        replaced.push((old_id, old_comp));
+                    }
+                    None => {
+                        self.hooks.inserted(id, &comp);
+                        self.owners.insert(id.index());
+                        self.record_added(id);
+                        added += 1;
+                    }
+                }
+                merged.push((id, comp), tick);
+            }
+            for (old_comp, old_tick) in old {
+                merged.push(old_comp, old_tick);
+            }
+
+            self.pages[page] = merged;
+            self.rank.inserted(page, added);
         }
+        replaced
     }
 
-    /// Returns the element if it was there
+    /// Returns the element if it was there.
+    ///
+    /// Errors if the index of the entity has a component from another generation
+    pub fn try_remove(&mut self, id: EntityHandle) -> Result<Option<T>, CompVecError> {
+        Ok(self.try_entry(id)?.remove())
+    }
+
+    /// Panicking version of `try_remove`
     pub fn remove(&mut self, id: EntityHandle) -> Option<T> {
-        if self.owners.contains(id.index()) {
-            self.owners.remove(id.index());
-            let comp_ind = self.owners.count_ones(0..id.index()); // exclude self
-            let (id_out, comp) = self.comps.remove(comp_ind);
-            assert_eq!(id_out, id);
-            Some(comp)
-        } else {
-            None
+        self.try_remove(id).unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Removes the components matching `pred` from the pages in `pages`,
+    /// passing them to `removed`, or dropping them if there is none.
+    /// Keeps `owners` and the rank index in sync in the same pass.
+    /// The kept components are marked as changed at `tick` if there is one
+    fn extract_from_pages(
+        &mut self,
+        pages: impl Iterator<Item = usize>,
+        tick: Option<u64>,
+        mut pred: impl FnMut(EntityHandle, &mut T) -> bool,
+        mut removed: Option<impl FnMut((EntityHandle, T))>,
+    ) {
+        for page_ind in pages {
+            self.dirty = true;
+            let page = std::mem::take(&mut self.pages[page_ind]);
+            let mut kept = Page::default();
+            let mut count = 0;
+            for ((id, mut comp), old_tick) in page.comps.into_iter().zip(page.ticks) {
+                if pred(id, &mut comp) {
+                    self.owners.remove(id.index());
+                    count += 1;
+                    self.hooks.removed(id, &comp);
+                    match &mut removed {
+                        Some(removed) => {
+                            self.record_removed(id, None);
+                            removed((id, comp));
+                        }
+                        None => self.record_removed(id, Some(comp)),
+                    }
+                } else {
+                    kept.push((id, comp), tick.unwrap_or(old_tick));
+                }
+            }
+            self.pages[page_ind] = kept;
+            self.rank.removed(page_ind, count);
         }
     }
 
-    /// Returns a slice of the underlying Vec
-    pub fn components(&self) -> &[(EntityHandle, T)] {
-        &self.comps
+    /// Only keeps the components for which `keep` returns true.
+    /// The kept components are marked as changed, since `keep` gets them mutably
+    pub fn retain(&mut self, mut keep: impl FnMut(EntityHandle, &mut T) -> bool) {
+        let tick = self.next_tick();
+        self.extract_from_pages(
+            0..self.pages.len(),
+            Some(tick),
+            |id, comp| !keep(id, comp),
+            None::<fn(_)>,
+        );
+    }
+
+    /// Removes and returns the components for which `pred` returns true,
+    /// in order of entity index. The kept components are marked as changed,
+    /// since `pred` gets them mutably
+    pub fn extract_if(
+        &mut self,
+        pred: impl FnMut(EntityHandle, &mut T) -> bool,
+    ) -> Vec<(EntityHandle, T)> {
+        let tick = self.next_tick();
+        let mut extracted = Vec::new();
+        self.extract_from_pages(
+            0..self.pages.len(),
+            Some(tick),
+            pred,
+            Some(|comp| extracted.push(comp)),
+        );
+        extracted
+    }
+
+    /// Removes and returns the components of the entities, in order of entity index.
+    ///
+    /// Only the pages containing one of the entities are compacted. Unlike `remove`,
+    /// a handle that doesn't match the stored one (stale generation) is ignored
+    pub fn remove_many(&mut self, ids: &[EntityHandle]) -> Vec<(EntityHandle, T)> {
+        let targets: std::collections::HashSet<_> = ids.iter().copied().collect();
+        let mut pages: Vec<_> = ids
+            .iter()
+            .map(|id| id.index() / PAGE_SIZE)
+            .filter(|page| *page < self.pages.len())
+            .collect();
+        pages.sort_unstable();
+        pages.dedup();
+
+        let mut removed = Vec::new();
+        self.extract_from_pages(
+            pages.into_iter(),
+            None,
+            |id, _| targets.contains(&id),
+            Some(|comp| removed.push(comp)),
+        );
+        removed
     }
 
-    /// Returns a reference to the component at the specified index in the underlying vec
+    /// Returns the non-empty pages of the underlying storage as slices,
+    /// in order of entity index
+    pub fn components(&self) -> impl Iterator<Item = &[(EntityHandle, T)]> {
+        self.pages
+            .iter()
+            .filter(|page| !page.comps.is_empty())
+            .map(|page| page.comps.as_slice())
+    }
+
+    /// Page and position in the page of the component at the specified index
+    fn try_select(&self, id: usize) -> Result<(usize, usize), CompVecError> {
+        self.rank.select(id).ok_or(CompVecError::OutOfBounds {
+            index: id,
+            len: self.len(),
+        })
+    }
+
+    /// Returns a reference to the component at the specified index in the underlying storage
+    pub fn try_get_comp_ind(&self, id: usize) -> Result<(EntityHandle, &T), CompVecError> {
+        let (page, comp_ind) = self.try_select(id)?;
+        let (handle, comp) = &self.pages[page].comps[comp_ind];
+        Ok((*handle, comp))
+    }
+
+    /// Returns a mutable reference to the component at the specified index in the underlying storage
+    pub fn try_get_mut_comp_ind(
+        &mut self,
+        id: usize,
+    ) -> Result<(EntityHandle, &mut T), CompVecError> {
+        let (page, comp_ind) = self.try_select(id)?;
+        let tick = self.next_tick();
+        let (handle, comp) = self.pages[page].get_mut(comp_ind, tick);
+        Ok((*handle, comp))
+    }
+
+    /// Panicking version of `try_get_comp_ind`
     pub fn get_comp_ind(&self, id: usize) -> (EntityHandle, &T) {
-        let (handle, comp) = &self.comps[id];
-        (*handle, comp)
+        self.try_get_comp_ind(id).unwrap_or_else(|e| panic!("{e}"))
     }
 
-    /// Returns a mutable reference to the component at the specified index in the underlying vec
+    /// Panicking version of `try_get_mut_comp_ind`
     pub fn get_mut_comp_ind(&mut self, id: usize) -> (EntityHandle, &mut T) {
-        let (handle, comp) = &mut self.comps[id];
-        (*handle, comp)
+        self.try_get_mut_comp_ind(id)
+            .unwrap_or_else(|e| panic!("{e}"))
     }
 
-    /// Iterator of the underlying vec. Can be used with `CompIter` to iterate
+    /// Iterator of the underlying storage. Can be used with `CompIter` to iterate
     /// over components with shared ownership
     pub fn iter(&self) -> crate::comp_iter::Iter<'_, T> {
-        crate::comp_iter::Iter::new(&self.comps, &self.owners)
+        crate::comp_iter::Iter::new(&self.pages, &self.owners)
     }
 
-    /// Mutable Iterator of the underlying vec. Can be used with `CompIter` to iterate
+    /// Mutable Iterator of the underlying storage. Can be used with `CompIter` to iterate
     /// over components with shared ownership
+    ///
+    /// Every component it hands out is marked as changed, written to or not.
+    /// In `CompIter`, that's only the components of the iterated entities
     pub fn iter_mut(&mut self) -> crate::comp_iter::IterMut<'_, T> {
-        crate::comp_iter::IterMut::new(&mut self.comps, &self.owners)
+        let tick = self.next_tick();
+        crate::comp_iter::IterMut::new(&mut self.pages, &self.owners, tick)
+    }
+
+    /// The last tick components were marked as changed at. Components changed
+    /// after calling this will be returned by `iter_changed_since(tick)`
+    pub fn change_tick(&self) -> u64 {
+        self.tick
+    }
+
+    /// Whether components were handed out mutably, inserted or removed since
+    /// the last `clear_dirty`. Used by `RollbackBuffer` to only copy the
+    /// storages that changed
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    pub fn clear_dirty(&mut self) {
+        self.dirty = false;
+    }
+
+    /// The tick the component of the entity was last changed at
+    pub fn last_changed(&self, id: EntityHandle) -> Option<u64> {
+        let (page, comp_ind) = self.try_locate(id).ok().flatten()?;
+        Some(self.pages[page].ticks[comp_ind])
+    }
+
+    /// Bitset of the entity indices whose component changed after `tick`
+    fn changed_since(&self, tick: u64) -> FixedBitSet {
+        let mut changed = FixedBitSet::with_capacity(self.owners.len());
+        for page in self.pages.iter() {
+            for ((id, _), comp_tick) in page.comps.iter().zip(page.ticks.iter()) {
+                if *comp_tick > tick {
+                    changed.insert(id.index());
+                }
+            }
+        }
+        changed
+    }
+
+    /// Iterator of the components changed after `tick`, see `change_tick`.
+    /// Can be used with `CompIter` to only iterate over entities whose component changed
+    pub fn iter_changed_since(&self, tick: u64) -> Changed<crate::comp_iter::Iter<'_, T>> {
+        Changed::new(self.iter(), self.changed_since(tick))
+    }
+
+    /// Mutable iterator of the components changed after `tick`, see `change_tick`.
+    /// Can be used with `CompIter` to only iterate over entities whose component changed
+    pub fn iter_mut_changed_since(
+        &mut self,
+        tick: u64,
+    ) -> Changed<crate::comp_iter::IterMut<'_, T>> {
+        let changed = self.changed_since(tick);
+        Changed::new(self.iter_mut(), changed)
     }
 
     pub fn owners(&self) -> &FixedBitSet {
         &self.owners
     }
 
+    /// Number of components
+    pub fn len(&self) -> usize {
+        self.rank.total()
+    }
+
     pub fn is_empty(&self) -> bool {
-        self.comps.is_empty()
+        self.len() == 0
+    }
+}
+
+impl<T: Clone> CompVec<T> {
+    /// Sets the components, their change ticks and the change tick back to
+    /// those of `saved`, keeping the hooks, the tracking and the not yet
+    /// drained added and removed components. Used by `RollbackBuffer`
+    pub(crate) fn restore_comps(&mut self, saved: &Self) {
+        self.pages.clone_from(&saved.pages);
+        self.owners.clone_from(&saved.owners);
+        self.rank.clone_from(&saved.rank);
+        self.tick = saved.tick;
+    }
+}
+
+impl<T> Extend<(EntityHandle, T)> for CompVec<T> {
+    /// Same as `insert_many`, dropping the replaced components
+    fn extend<I: IntoIterator<Item = (EntityHandle, T)>>(&mut self, iter: I) {
+        self.insert_many(iter);
+    }
+}
+
+impl<T: PartialEq> PartialEq for CompVec<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+impl<T: Eq> Eq for CompVec<T> {}
+
+impl<T: Hash> Hash for CompVec<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.len().hash(state);
+        for (id, comp) in self.iter() {
+            id.hash(state);
+            comp.hash(state);
+        }
+    }
+}
+
+impl<T> CompStorage for CompVec<T> {
+    fn owners(&self) -> &FixedBitSet {
+        &self.owners
+    }
+
+    /// With `Tracking::Values`, the component is kept until `drain_removed`
+    fn remove_entity(&mut self, handle: EntityHandle) {
+        if let Entry::Occupied(entry) = self.entry(handle) {
+            entry.discard();
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
     }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::{CompVec, EntityHandleCounter};
+    use crate::{CompIter, CompVec, CompVecError, EntityHandleCounter, Entry, GetManyError};
 
     #[test]
     fn test_get2_mut() {
@@ -188,4 +861,326 @@ mod test {
             assert_eq!(s3, Some(&mut "hello3".to_string()));
         }
     }
+
+    #[test]
+    fn test_get_many_entities() {
+        let mut v = CompVec::<usize>::default();
+        let mut handles = EntityHandleCounter::default();
+        let ids: Vec<_> = (0..1000).map(|_| handles.next_handle()).collect();
+
+        for id in ids.iter().rev().filter(|id| id.index() % 3 != 0) {
+            v.insert(*id, id.index());
+        }
+        for id in ids.iter().filter(|id| id.index() % 5 == 0) {
+            v.remove(*id);
+        }
+
+        for id in ids.iter() {
+            let expected = (id.index() % 3 != 0 && id.index() % 5 != 0).then_some(id.index());
+            assert_eq!(v.get(*id).copied(), expected);
+        }
+    }
+
+    #[test]
+    fn test_iter_across_pages() {
+        let mut v1 = CompVec::<usize>::default();
+        let mut v2 = CompVec::<usize>::default();
+        let mut handles = EntityHandleCounter::default();
+        let ids: Vec<_> = (0..2000).map(|_| handles.next_handle()).collect();
+
+        for id in ids.iter().filter(|id| id.index() % 2 == 0) {
+            v1.insert(*id, id.index());
+        }
+        for id in ids.iter().filter(|id| id.index() % 300 == 0) {
+            v2.insert(*id, id.index());
+        }
+
+        let all: Vec<_> = v1.iter().map(|(id, comp)| (id.index(), *comp)).collect();
+        let expected: Vec<_> = (0..2000).step_by(2).map(|i| (i, i)).collect();
+        assert_eq!(all, expected);
+        assert_eq!(v1.len(), 1000);
+        assert_eq!(v1.get_comp_ind(500), (ids[1000], &1000));
+
+        for (id, comp1, comp2) in CompIter::from((v2.iter_mut(), v1.iter_mut())) {
+            assert_eq!(*comp1, id.index());
+            assert_eq!(*comp2, id.index());
+            *comp2 += 1;
+        }
+        let joined: Vec<_> = CompIter::from((v1.iter(), v2.iter().optional()))
+            .into_iter()
+            .filter_map(|(id, comp1, comp2)| comp2.map(|_| (id.index(), *comp1)))
+            .collect();
+        let expected: Vec<_> = (0..2000).step_by(300).map(|i| (i, i + 1)).collect();
+        assert_eq!(joined, expected);
+    }
+
+    #[test]
+    fn test_insert_many() {
+        let mut v = CompVec::<usize>::default();
+        let mut handles = EntityHandleCounter::default();
+        let ids: Vec<_> = (0..1000).map(|_| handles.next_handle()).collect();
+
+        for id in ids.iter().step_by(7) {
+            v.insert(*id, id.index());
+        }
+        let replaced = v.insert_many(
+            ids.iter()
+                .rev()
+                .filter(|id| id.index() % 2 == 0)
+                .map(|id| (*id, id.index() + 1))
+                .chain([(ids[1], 1), (ids[1], 2)]),
+        );
+
+        let expected_replaced: Vec<_> = std::iter::once((ids[0], 0))
+            .chain(std::iter::once((ids[1], 1)))
+            .chain(
+                ids.iter()
+                    .skip(1)
+                    .filter(|id| id.index() % 14 == 0)
+                    .map(|id| (*id, id.index())),
+            )
+            .collect();
+        assert_eq!(replaced, expected_replaced);
+
+        let all: Vec<_> = v.iter().map(|(id, comp)| (id.index(), *comp)).collect();
+        let expected: Vec<_> = (0..1000)
+            .filter_map(|i| match i {
+                1 => Some((1, 2)),
+                i if i % 2 == 0 => Some((i, i + 1)),
+                i if i % 7 == 0 => Some((i, i)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(all, expected);
+        assert_eq!(v.len(), expected.len());
+        assert_eq!(v.owners().count_ones(..), expected.len());
+    }
+
+    #[test]
+    fn test_insert_many_stale() {
+        let mut v = CompVec::<usize>::default();
+        let mut handles = EntityHandleCounter::default();
+        let ids: Vec<_> = (0..3).map(|_| handles.next_handle()).collect();
+        v.insert_many(ids.iter().map(|id| (*id, id.index())));
+        handles.entity_deleted(ids[1]);
+        let respawned = handles.next_handle();
+
+        // valid handles sorted before and after the stale one
+        for batch in [
+            vec![(ids[0], 10), (respawned, 11)],
+            vec![(ids[2], 12), (ids[0], 10), (respawned, 11)],
+        ] {
+            let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                v.insert_many(batch);
+            }));
+            assert!(result.is_err());
+            let all: Vec<_> = v.iter().map(|(id, comp)| (id, *comp)).collect();
+            assert_eq!(all, vec![(ids[0], 0), (ids[1], 1), (ids[2], 2)]);
+        }
+
+        let mut empty = CompVec::<usize>::default();
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            empty.insert_many([(ids[1], 1), (respawned, 11)]);
+        }));
+        assert!(result.is_err());
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn test_bulk_remove() {
+        let mut v = CompVec::<usize>::default();
+        let mut handles = EntityHandleCounter::default();
+        let ids: Vec<_> = (0..1000).map(|_| handles.next_handle()).collect();
+        v.insert_many(ids.iter().map(|id| (*id, id.index())));
+
+        v.retain(|id, comp| {
+            *comp += 1;
+            id.index() % 2 == 0
+        });
+        assert_eq!(v.len(), 500);
+
+        let extracted = v.extract_if(|_, comp| *comp % 3 == 0);
+        let expected: Vec<_> = (0..1000)
+            .filter(|i| i % 2 == 0 && (i + 1) % 3 == 0)
+            .map(|i| (ids[i], i + 1))
+            .collect();
+        assert_eq!(extracted, expected);
+
+        handles.entity_deleted(ids[4]);
+        let stale = handles.next_handle();
+        assert_eq!(stale.index(), 4);
+        let removed = v.remove_many(&[ids[996], stale, ids[0], ids[3], ids[0]]);
+        assert_eq!(removed, vec![(ids[0], 1), (ids[996], 997)]);
+
+        let all: Vec<_> = v.iter().map(|(id, comp)| (id.index(), *comp)).collect();
+        let expected: Vec<_> = (0..1000)
+            .filter(|i| i % 2 == 0 && (i + 1) % 3 != 0 && *i != 0 && *i != 996)
+            .map(|i| (i, i + 1))
+            .collect();
+        assert_eq!(all, expected);
+        assert_eq!(v.len(), expected.len());
+        assert_eq!(v.owners().count_ones(..), expected.len());
+    }
+
+    #[test]
+    fn test_entry() {
+        let mut v = CompVec::<u32>::default();
+        let mut handles = EntityHandleCounter::default();
+        let id1 = handles.next_handle();
+        let id2 = handles.next_handle();
+
+        *v.entry(id1).or_insert(1) += 10;
+        assert_eq!(v.get(id1), Some(&11));
+        *v.entry(id1).and_modify(|comp| *comp *= 2).or_default() += 1;
+        assert_eq!(v.get(id1), Some(&23));
+
+        assert_eq!(*v.entry(id2).and_modify(|comp| *comp = 5).or_default(), 0);
+        assert_eq!(*v.entry(id2).or_insert_with(|| 7), 0);
+
+        match v.entry(id2) {
+            Entry::Occupied(mut entry) => {
+                assert_eq!(entry.insert(3), 0);
+                assert_eq!(entry.remove(), 3);
+            }
+            Entry::Vacant(_) => unreachable!(),
+        }
+        assert_eq!(v.entry(id2).remove(), None);
+        assert_eq!(v.entry(id1).remove(), Some(23));
+        assert!(v.is_empty());
+    }
+
+    #[test]
+    fn test_get_many_mut() {
+        let mut v = CompVec::<usize>::default();
+        let mut handles = EntityHandleCounter::default();
+        let ids: Vec<_> = (0..600).map(|_| handles.next_handle()).collect();
+        for id in ids.iter().filter(|id| id.index() != 3) {
+            v.insert(*id, id.index());
+        }
+
+        let [c1, c2, c3, c4] = v.get_many_mut([ids[500], ids[2], ids[3], ids[1]]).unwrap();
+        assert_eq!(
+            (c1.copied(), c2.copied(), c3, c4.copied()),
+            (Some(500), Some(2), None, Some(1))
+        );
+        for comp in v
+            .get_many_mut([ids[257], ids[256], ids[0]])
+            .unwrap()
+            .into_iter()
+            .flatten()
+        {
+            *comp += 1000;
+        }
+        assert_eq!(v.get(ids[256]), Some(&1256));
+        assert_eq!(v.get(ids[257]), Some(&1257));
+        assert_eq!(v.get(ids[0]), Some(&1000));
+
+        assert_eq!(
+            v.get_many_mut([ids[1], ids[2], ids[1]]).err(),
+            Some(GetManyError::DuplicateHandle(ids[1]))
+        );
+
+        v.remove(ids[4]);
+        handles.entity_deleted(ids[4]);
+        let new = handles.next_handle();
+        v.insert(new, 4);
+        assert_eq!(
+            v.get_many_mut([ids[1], ids[4]]).err(),
+            Some(GetManyError::Comp(CompVecError::StaleGeneration {
+                stored: new,
+                requested: ids[4]
+            }))
+        );
+    }
+
+    #[test]
+    fn test_fallible() {
+        let mut v = CompVec::<usize>::default();
+        let mut handles = EntityHandleCounter::default();
+        let old = handles.next_handle();
+        v.insert(old, 1);
+        handles.entity_deleted(old);
+        let new = handles.next_handle();
+        assert_eq!(old.index(), new.index());
+
+        let stale = CompVecError::StaleGeneration {
+            stored: old,
+            requested: new,
+        };
+        assert_eq!(v.try_get(new), Err(stale));
+        assert_eq!(v.get(new), None);
+        assert_eq!(v.try_insert(new, 2), Err(stale));
+        assert_eq!(v.try_remove(new), Err(stale));
+        assert_eq!(v.try_get(old), Ok(Some(&1)));
+
+        assert_eq!(
+            v.try_get_comp_ind(1).err(),
+            Some(CompVecError::OutOfBounds { index: 1, len: 1 })
+        );
+        assert_eq!(v.try_remove(old), Ok(Some(1)));
+        assert_eq!(v.try_insert(new, 2), Ok(None));
+        assert_eq!(v.try_get_comp_ind(0), Ok((new, &2)));
+    }
+
+    #[test]
+    fn test_eq() {
+        let mut handles = EntityHandleCounter::default();
+        let ids: Vec<_> = (0..600).map(|_| handles.next_handle()).collect();
+        let mut v1 = CompVec::<usize>::default();
+        v1.insert_many(ids.iter().map(|id| (*id, id.index())));
+        let mut v2 = CompVec::<usize>::default();
+        for id in ids.iter().rev() {
+            v2.insert(*id, 0);
+            *v2.get_mut(*id).unwrap() = id.index();
+        }
+        // change ticks are not compared
+        assert_eq!(v1, v2);
+        assert_eq!(v1, v1.clone());
+
+        v2.remove(ids[500]);
+        assert_ne!(v1, v2);
+        v2.insert(ids[500], 0);
+        assert_ne!(v1, v2);
+    }
+
+    #[test]
+    fn test_change_ticks() {
+        let mut pos = CompVec::<u32>::default();
+        let mut vel = CompVec::<u32>::default();
+        let mut handles = EntityHandleCounter::default();
+        let ids: Vec<_> = (0..600).map(|_| handles.next_handle()).collect();
+        pos.insert_many(ids.iter().map(|id| (*id, 0)));
+        vel.insert_many(ids.iter().map(|id| (*id, id.index() as u32)));
+
+        let tick = pos.change_tick();
+        assert_eq!(pos.iter_changed_since(tick).count(), 0);
+
+        *pos.get_mut(ids[3]).unwrap() += 1;
+        *pos.entry(ids[300]).or_default() += 1;
+        let changed: Vec<_> = pos.iter_changed_since(tick).map(|(id, _)| id).collect();
+        assert_eq!(changed, vec![ids[3], ids[300]]);
+        assert!(pos.last_changed(ids[3]).unwrap() > tick);
+        assert!(pos.last_changed(ids[4]).unwrap() <= tick);
+
+        let tick = pos.change_tick();
+        for (_id, pos, vel) in CompIter::from((pos.iter_mut(), vel.iter())) {
+            if *vel % 100 == 0 {
+                *pos += 1;
+            }
+        }
+        // iter_mut hands out every component mutably
+        assert_eq!(pos.iter_changed_since(tick).count(), 600);
+
+        let tick = pos.change_tick();
+        pos.insert(ids[10], 5);
+        pos.retain(|id, _| id.index() != 20);
+        let tick2 = pos.change_tick();
+        pos.insert(ids[599], 7);
+        assert_eq!(pos.iter_changed_since(tick).count(), 599);
+        let joined: Vec<_> = CompIter::from((vel.iter(), pos.iter_changed_since(tick2)))
+            .into_iter()
+            .map(|(id, vel, pos)| (id, *vel, *pos))
+            .collect();
+        assert_eq!(joined, vec![(ids[599], 599, 7)]);
+    }
 }
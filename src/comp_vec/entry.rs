@@ -0,0 +1,191 @@
+use super::{CompVec, CompVecError, Page};
+use crate::EntityHandle;
+
+/// A view into the component of a single entity in a `CompVec`,
+/// which may either be vacant or occupied. Made by `CompVec::entry`.
+pub enum Entry<'a, T> {
+    Occupied(OccupiedEntry<'a, T>),
+    Vacant(VacantEntry<'a, T>),
+}
+
+/// An entity that has a component in the `CompVec`
+pub struct OccupiedEntry<'a, T> {
+    vec: &'a mut CompVec<T>,
+    id: EntityHandle,
+    page: usize,
+    comp_ind: usize,
+}
+
+/// An entity that doesn't have a component in the `CompVec`
+pub struct VacantEntry<'a, T> {
+    vec: &'a mut CompVec<T>,
+    id: EntityHandle,
+    page: usize,
+    comp_ind: usize,
+}
+
+impl<T> CompVec<T> {
+    /// Gets the entry of the entity for in-place manipulation.
+    /// The position of the component is only looked up once.
+    ///
+    /// Errors if the index of the entity has a component from another generation
+    pub fn try_entry(&mut self, id: EntityHandle) -> Result<Entry<'_, T>, CompVecError> {
+        let (page, comp_ind) = self.locate(id.index());
+        if self.owners.contains(id.index()) {
+            let stored = self.pages[page].comps[comp_ind].0;
+            if stored != id {
+                return Err(CompVecError::StaleGeneration {
+                    stored,
+                    requested: id,
+                });
+            }
+            Ok(Entry::Occupied(OccupiedEntry {
+                vec: self,
+                id,
+                page,
+                comp_ind,
+            }))
+        } else {
+            Ok(Entry::Vacant(VacantEntry {
+                vec: self,
+                id,
+                page,
+                comp_ind,
+            }))
+        }
+    }
+
+    /// Panicking version of `try_entry`
+    pub fn entry(&mut self, id: EntityHandle) -> Entry<'_, T> {
+        self.try_entry(id).unwrap_or_else(|e| panic!("{e}"))
+    }
+}
+
+impl<'a, T> Entry<'a, T> {
+    pub fn handle(&self) -> EntityHandle {
+        match self {
+            Entry::Occupied(entry) => entry.id,
+            Entry::Vacant(entry) => entry.id,
+        }
+    }
+
+    /// Inserts `default` if the entity doesn't have the component,
+    /// returns a mutable reference to the component
+    pub fn or_insert(self, default: T) -> &'a mut T {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default),
+        }
+    }
+
+    /// Inserts the result of `default` if the entity doesn't have the component,
+    /// returns a mutable reference to the component
+    pub fn or_insert_with(self, default: impl FnOnce() -> T) -> &'a mut T {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default()),
+        }
+    }
+
+    /// Modifies the component if the entity has it
+    #[must_use]
+    pub fn and_modify(mut self, f: impl FnOnce(&mut T)) -> Self {
+        if let Entry::Occupied(entry) = &mut self {
+            f(entry.get_mut());
+        }
+        self
+    }
+
+    /// Removes the component if the entity has it
+    pub fn remove(self) -> Option<T> {
+        match self {
+            Entry::Occupied(entry) => Some(entry.remove()),
+            Entry::Vacant(_) => None,
+        }
+    }
+}
+
+impl<'a, T: Default> Entry<'a, T> {
+    /// Inserts `T::default()` if the entity doesn't have the component,
+    /// returns a mutable reference to the component
+    pub fn or_default(self) -> &'a mut T {
+        self.or_insert_with(T::default)
+    }
+}
+
+impl<'a, T> OccupiedEntry<'a, T> {
+    pub fn handle(&self) -> EntityHandle {
+        self.id
+    }
+
+    pub fn get(&self) -> &T {
+        &self.vec.pages[self.page].comps[self.comp_ind].1
+    }
+
+    /// Marks the component as changed
+    pub fn get_mut(&mut self) -> &mut T {
+        let tick = self.vec.next_tick();
+        &mut self.vec.pages[self.page].get_mut(self.comp_ind, tick).1
+    }
+
+    /// Converts the entry into a mutable reference to the component
+    /// with the lifetime of the `CompVec`. Marks the component as changed
+    pub fn into_mut(self) -> &'a mut T {
+        let tick = self.vec.next_tick();
+        &mut self.vec.pages[self.page].get_mut(self.comp_ind, tick).1
+    }
+
+    /// Replaces the component, returns the previous one
+    pub fn insert(&mut self, comp: T) -> T {
+        let old = std::mem::replace(self.get_mut(), comp);
+        self.vec.hooks.replaced(self.id, &old);
+        old
+    }
+
+    fn take(self) -> (&'a mut CompVec<T>, T) {
+        self.vec.dirty = true;
+        self.vec.owners.remove(self.id.index());
+        let (_, comp) = self.vec.pages[self.page].remove(self.comp_ind);
+        self.vec.rank.removed(self.page, 1);
+        self.vec.hooks.removed(self.id, &comp);
+        (self.vec, comp)
+    }
+
+    /// Removes the component from the `CompVec`
+    pub fn remove(self) -> T {
+        let id = self.id;
+        let (vec, comp) = self.take();
+        vec.record_removed(id, None);
+        comp
+    }
+
+    /// Removes the component, dropping it or keeping it in the removed
+    /// components depending on the tracking of the `CompVec`
+    pub(super) fn discard(self) {
+        let id = self.id;
+        let (vec, comp) = self.take();
+        vec.record_removed(id, Some(comp));
+    }
+}
+
+impl<'a, T> VacantEntry<'a, T> {
+    pub fn handle(&self) -> EntityHandle {
+        self.id
+    }
+
+    /// Inserts the component, returns a mutable reference to it
+    pub fn insert(self, comp: T) -> &'a mut T {
+        let vec = self.vec;
+        vec.owners.grow_and_insert(self.id.index());
+        if self.page >= vec.pages.len() {
+            vec.pages.resize_with(self.page + 1, Page::default);
+        }
+        let tick = vec.next_tick();
+        vec.pages[self.page].insert(self.comp_ind, (self.id, comp), tick);
+        vec.rank.inserted(self.page, 1);
+        vec.record_added(self.id);
+        let comp = &mut vec.pages[self.page].comps[self.comp_ind].1;
+        vec.hooks.inserted(self.id, comp);
+        comp
+    }
+}
@@ -0,0 +1,73 @@
+use super::CompVec;
+use crate::EntityHandle;
+use fixedbitset::FixedBitSet;
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// The components in order of entity index, serialized as a sequence
+struct Comps<'a, T>(&'a CompVec<T>);
+
+impl<T: Serialize> Serialize for Comps<'_, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.0.iter())
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename = "CompVec")]
+struct CompVecRef<'a, T: Serialize> {
+    #[serde(serialize_with = "crate::bitset_serde::serialize")]
+    owners: &'a FixedBitSet,
+    comps: Comps<'a, T>,
+}
+
+/// Serialized as the owners bitset and the `(EntityHandle, T)` pairs in order of
+/// entity index. Change ticks, tracking and hooks are not serialized
+impl<T: Serialize> Serialize for CompVec<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        CompVecRef {
+            owners: &self.owners,
+            comps: Comps(self),
+        }
+        .serialize(serializer)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename = "CompVec")]
+struct CompVecData<T> {
+    #[serde(with = "crate::bitset_serde")]
+    owners: FixedBitSet,
+    comps: Vec<(EntityHandle, T)>,
+}
+
+/// Errors if the components are not sorted by entity index,
+/// or if their entity indices don't match the owners bitset
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for CompVec<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let data = CompVecData::<T>::deserialize(deserializer)?;
+        if let Some(pair) = data
+            .comps
+            .windows(2)
+            .find(|pair| pair[0].0.index() >= pair[1].0.index())
+        {
+            return Err(D::Error::custom(format!(
+                "components of {:?} and {:?} are not sorted by entity index",
+                pair[0].0, pair[1].0
+            )));
+        }
+        if data.owners.count_ones(..) != data.comps.len()
+            || data
+                .comps
+                .iter()
+                .any(|(id, _)| !data.owners.contains(id.index()))
+        {
+            return Err(D::Error::custom(
+                "owners bitset doesn't match the component handles",
+            ));
+        }
+        let mut vec = CompVec::default();
+        vec.insert_many(data.comps);
+        Ok(vec)
+    }
+}
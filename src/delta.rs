@@ -0,0 +1,455 @@
+use crate::{
+    CompVec, DenseCompVec, EntityHandle, EntityHandleCounter, IndexedCompVec, SparseCompVec,
+    TagVec, UniqueCompVec,
+};
+use fixedbitset::FixedBitSet;
+use std::collections::HashSet;
+use std::hash::Hash;
+
+/// Difference between two states of an `EntityHandleCounter`, see `EntityHandleCounter::delta`
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HandlesDelta {
+    pub spawned: Vec<EntityHandle>,
+    pub despawned: Vec<EntityHandle>,
+    /// Indices past the end of the older counter that were given out and freed
+    /// again, so every new index is in `spawned` or here
+    pub(crate) freed: Vec<usize>,
+    pub(crate) next: usize,
+    pub(crate) generation: u32,
+    pub(crate) removed: bool,
+}
+
+/// Error of `apply_delta` for a delta that doesn't fit the state it is applied to,
+/// like a corrupted one or one made from another state
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeltaError {
+    /// A list of the delta isn't sorted by entity index, or has an entity index twice
+    Unsorted,
+    /// A removed, changed or despawned entity isn't there with this generation
+    NotFound(EntityHandle),
+    /// The index of an inserted or spawned entity is already taken
+    AlreadyPresent(EntityHandle),
+    /// A spawned handle the counter can't have given out, a live entity past
+    /// the end of the newer counter, or an inserted component of an entity
+    /// that isn't alive after the delta
+    InvalidHandle(EntityHandle),
+    /// An index past the end of the older counter that the delta doesn't list,
+    /// or a freed index that isn't one of them
+    InvalidIndex(usize),
+    /// A `UniqueCompVec` value that would belong to two entities
+    NotUnique(EntityHandle),
+}
+
+impl std::fmt::Display for DeltaError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DeltaError::Unsorted => write!(f, "delta lists are not sorted by entity index"),
+            DeltaError::NotFound(handle) => write!(f, "entity {handle:?} is not there"),
+            DeltaError::AlreadyPresent(handle) => {
+                write!(f, "index of entity {handle:?} is already taken")
+            }
+            DeltaError::InvalidHandle(handle) => write!(f, "invalid entity handle {handle:?}"),
+            DeltaError::InvalidIndex(index) => write!(f, "invalid new entity index {index}"),
+            DeltaError::NotUnique(handle) => {
+                write!(f, "value of entity {handle:?} belongs to another entity")
+            }
+        }
+    }
+}
+
+impl std::error::Error for DeltaError {}
+
+/// Difference between two states of a component storage, see `DeltaStorage`.
+/// Every list is sorted by entity index
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CompDelta<T> {
+    pub inserted: Vec<(EntityHandle, T)>,
+    pub removed: Vec<EntityHandle>,
+    pub changed: Vec<(EntityHandle, T)>,
+}
+
+impl<T> Default for CompDelta<T> {
+    fn default() -> Self {
+        Self {
+            inserted: Default::default(),
+            removed: Default::default(),
+            changed: Default::default(),
+        }
+    }
+}
+
+impl<T> CompDelta<T> {
+    pub fn is_empty(&self) -> bool {
+        self.inserted.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+
+    /// Diffs two iterators of components sorted by entity index.
+    /// A component whose entity index got reused by another generation
+    /// is removed then inserted
+    fn between<'a>(
+        old: impl Iterator<Item = (EntityHandle, &'a T)>,
+        new: impl Iterator<Item = (EntityHandle, &'a T)>,
+    ) -> Self
+    where
+        T: PartialEq + Clone + 'a,
+    {
+        let mut delta = Self::default();
+        let mut old = old.peekable();
+        let mut new = new.peekable();
+        loop {
+            match (old.peek(), new.peek()) {
+                (None, None) => break,
+                (Some((old_id, _)), Some((new_id, _))) if old_id.index() == new_id.index() => {
+                    let (old_id, old_comp) = old.next().unwrap();
+                    let (new_id, new_comp) = new.next().unwrap();
+                    if old_id != new_id {
+                        delta.removed.push(old_id);
+                        delta.inserted.push((new_id, new_comp.clone()));
+                    } else if old_comp != new_comp {
+                        delta.changed.push((new_id, new_comp.clone()));
+                    }
+                }
+                (Some((old_id, _)), Some((new_id, _))) if new_id.index() < old_id.index() => {
+                    let (new_id, new_comp) = new.next().unwrap();
+                    delta.inserted.push((new_id, new_comp.clone()));
+                }
+                (None, Some(_)) => {
+                    let (new_id, new_comp) = new.next().unwrap();
+                    delta.inserted.push((new_id, new_comp.clone()));
+                }
+                (Some(_), _) => delta.removed.push(old.next().unwrap().0),
+            }
+        }
+        delta
+    }
+
+    /// Checks the lists are sorted, the removed and changed components are in the storage
+    /// and the inserted ones aren't and belong to entities alive in `handles`,
+    /// `has` telling if the storage has a component of the entity
+    fn check(
+        &self,
+        owners: &FixedBitSet,
+        handles: &EntityHandleCounter,
+        has: impl Fn(EntityHandle) -> bool,
+    ) -> Result<(), DeltaError> {
+        fn sorted<'a>(ids: impl Iterator<Item = &'a EntityHandle>) -> Result<(), DeltaError> {
+            let mut previous = None;
+            for id in ids {
+                if previous.is_some_and(|previous| previous >= id.index()) {
+                    return Err(DeltaError::Unsorted);
+                }
+                previous = Some(id.index());
+            }
+            Ok(())
+        }
+        sorted(self.removed.iter())?;
+        sorted(self.inserted.iter().map(|(id, _)| id))?;
+        sorted(self.changed.iter().map(|(id, _)| id))?;
+
+        let is_removed = |index: usize| {
+            self.removed
+                .binary_search_by_key(&index, |id| id.index())
+                .is_ok()
+        };
+        for id in &self.removed {
+            if !has(*id) {
+                return Err(DeltaError::NotFound(*id));
+            }
+        }
+        for (id, _) in &self.changed {
+            if !has(*id) || is_removed(id.index()) {
+                return Err(DeltaError::NotFound(*id));
+            }
+        }
+        for (id, _) in &self.inserted {
+            if handles.handle_at(id.index()) != Some(*id) {
+                return Err(DeltaError::InvalidHandle(*id));
+            }
+            if owners.contains(id.index()) && !is_removed(id.index()) {
+                return Err(DeltaError::AlreadyPresent(*id));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A component storage that can be diffed against a newer state of itself,
+/// to send only the differences to replicas. Used by `#[world(delta)]`
+pub trait DeltaStorage {
+    type Delta;
+
+    /// What changed from `self` to `newer`
+    fn delta(&self, newer: &Self) -> Self::Delta;
+
+    /// Errors if the delta can't be applied to `self`, like a corrupted one
+    /// or one made from another state. `handles` is the state of the entity
+    /// handles after the delta, the inserted components must belong to live entities
+    fn check_delta(
+        &self,
+        delta: &Self::Delta,
+        handles: &EntityHandleCounter,
+    ) -> Result<(), DeltaError>;
+
+    /// Turns `self` into the newer state of the delta,
+    /// leaving `self` unchanged if `check_delta` errors
+    fn apply_delta(
+        &mut self,
+        delta: Self::Delta,
+        handles: &EntityHandleCounter,
+    ) -> Result<(), DeltaError>;
+}
+
+impl<T: PartialEq + Clone> DeltaStorage for CompVec<T> {
+    type Delta = CompDelta<T>;
+
+    /// Walks both `CompVec`s in entity index order, only comparing the
+    /// components of the entities in both
+    fn delta(&self, newer: &Self) -> CompDelta<T> {
+        CompDelta::between(self.iter(), newer.iter())
+    }
+
+    fn check_delta(
+        &self,
+        delta: &CompDelta<T>,
+        handles: &EntityHandleCounter,
+    ) -> Result<(), DeltaError> {
+        delta.check(self.owners(), handles, |id| self.get(id).is_some())
+    }
+
+    fn apply_delta(
+        &mut self,
+        delta: CompDelta<T>,
+        handles: &EntityHandleCounter,
+    ) -> Result<(), DeltaError> {
+        self.check_delta(&delta, handles)?;
+        self.remove_many(&delta.removed);
+        self.insert_many(delta.inserted.into_iter().chain(delta.changed));
+        Ok(())
+    }
+}
+
+impl<T: PartialEq + Clone> DeltaStorage for DenseCompVec<T> {
+    type Delta = CompDelta<T>;
+
+    fn delta(&self, newer: &Self) -> CompDelta<T> {
+        CompDelta::between(self.iter(), newer.iter())
+    }
+
+    fn check_delta(
+        &self,
+        delta: &CompDelta<T>,
+        handles: &EntityHandleCounter,
+    ) -> Result<(), DeltaError> {
+        delta.check(self.owners(), handles, |id| self.get(id).is_some())
+    }
+
+    fn apply_delta(
+        &mut self,
+        delta: CompDelta<T>,
+        handles: &EntityHandleCounter,
+    ) -> Result<(), DeltaError> {
+        self.check_delta(&delta, handles)?;
+        for id in delta.removed {
+            self.remove(id);
+        }
+        for (id, comp) in delta.inserted.into_iter().chain(delta.changed) {
+            self.insert(id, comp);
+        }
+        Ok(())
+    }
+}
+
+impl<T: PartialEq + Clone> DeltaStorage for SparseCompVec<T> {
+    type Delta = CompDelta<T>;
+
+    fn delta(&self, newer: &Self) -> CompDelta<T> {
+        fn sorted<T>(vec: &SparseCompVec<T>) -> Vec<(EntityHandle, &T)> {
+            let mut comps: Vec<_> = vec.iter().collect();
+            comps.sort_unstable_by_key(|(id, _)| id.index());
+            comps
+        }
+        CompDelta::between(sorted(self).into_iter(), sorted(newer).into_iter())
+    }
+
+    fn check_delta(
+        &self,
+        delta: &CompDelta<T>,
+        handles: &EntityHandleCounter,
+    ) -> Result<(), DeltaError> {
+        delta.check(self.owners(), handles, |id| self.get(id).is_some())
+    }
+
+    fn apply_delta(
+        &mut self,
+        delta: CompDelta<T>,
+        handles: &EntityHandleCounter,
+    ) -> Result<(), DeltaError> {
+        self.check_delta(&delta, handles)?;
+        for id in delta.removed {
+            self.remove(id);
+        }
+        for (id, comp) in delta.inserted.into_iter().chain(delta.changed) {
+            self.insert(id, comp);
+        }
+        Ok(())
+    }
+}
+
+impl DeltaStorage for TagVec {
+    type Delta = CompDelta<()>;
+
+    fn delta(&self, newer: &Self) -> CompDelta<()> {
+        CompDelta::between(
+            self.iter().map(|id| (id, &())),
+            newer.iter().map(|id| (id, &())),
+        )
+    }
+
+    fn check_delta(
+        &self,
+        delta: &CompDelta<()>,
+        handles: &EntityHandleCounter,
+    ) -> Result<(), DeltaError> {
+        delta.check(self.owners(), handles, |id| self.contains(id))
+    }
+
+    fn apply_delta(
+        &mut self,
+        delta: CompDelta<()>,
+        handles: &EntityHandleCounter,
+    ) -> Result<(), DeltaError> {
+        self.check_delta(&delta, handles)?;
+        for id in delta.removed {
+            self.remove(id);
+        }
+        for (id, ()) in delta.inserted {
+            self.insert(id);
+        }
+        Ok(())
+    }
+}
+
+impl<T: Hash + Eq + Clone> DeltaStorage for IndexedCompVec<T> {
+    type Delta = CompDelta<T>;
+
+    fn delta(&self, newer: &Self) -> CompDelta<T> {
+        self.comps().delta(newer.comps())
+    }
+
+    fn check_delta(
+        &self,
+        delta: &CompDelta<T>,
+        handles: &EntityHandleCounter,
+    ) -> Result<(), DeltaError> {
+        delta.check(self.owners(), handles, |id| self.get(id).is_some())
+    }
+
+    fn apply_delta(
+        &mut self,
+        delta: CompDelta<T>,
+        handles: &EntityHandleCounter,
+    ) -> Result<(), DeltaError> {
+        self.check_delta(&delta, handles)?;
+        for id in delta.removed {
+            self.remove(id);
+        }
+        for (id, comp) in delta.inserted.into_iter().chain(delta.changed) {
+            self.insert(id, comp);
+        }
+        Ok(())
+    }
+}
+
+impl<T: Hash + Eq + Clone> DeltaStorage for UniqueCompVec<T> {
+    type Delta = CompDelta<T>;
+
+    fn delta(&self, newer: &Self) -> CompDelta<T> {
+        self.comps().delta(newer.comps())
+    }
+
+    /// Also checks the new values only belong to one entity once the removed
+    /// and changed components are gone
+    fn check_delta(
+        &self,
+        delta: &CompDelta<T>,
+        handles: &EntityHandleCounter,
+    ) -> Result<(), DeltaError> {
+        delta.check(self.owners(), handles, |id| self.get(id).is_some())?;
+        let changed = delta.changed.iter().map(|(id, _)| id);
+        let freed: HashSet<_> = delta.removed.iter().chain(changed).collect();
+        let mut values = HashSet::new();
+        for (id, comp) in delta.inserted.iter().chain(&delta.changed) {
+            let taken = self
+                .handle_with(comp)
+                .is_some_and(|owner| !freed.contains(&owner));
+            if taken || !values.insert(comp) {
+                return Err(DeltaError::NotUnique(*id));
+            }
+        }
+        Ok(())
+    }
+
+    /// The changed components are removed before inserting the new values,
+    /// so entities can swap values
+    fn apply_delta(
+        &mut self,
+        delta: CompDelta<T>,
+        handles: &EntityHandleCounter,
+    ) -> Result<(), DeltaError> {
+        self.check_delta(&delta, handles)?;
+        for id in delta
+            .removed
+            .into_iter()
+            .chain(delta.changed.iter().map(|(id, _)| *id))
+        {
+            self.remove(id);
+        }
+        for (id, comp) in delta.inserted.into_iter().chain(delta.changed) {
+            self.insert(id, comp)
+                .unwrap_or_else(|_| unreachable!("checked by check_delta"));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{CompDelta, CompVec, DeltaStorage, EntityHandleCounter};
+
+    #[test]
+    fn test_comp_vec_delta() {
+        let mut handles = EntityHandleCounter::default();
+        let ids: Vec<_> = (0..1000).map(|_| handles.next_handle()).collect();
+        let mut old = CompVec::<usize>::default();
+        old.insert_many(ids.iter().step_by(2).map(|id| (*id, id.index())));
+        assert_eq!(CompVec::default().delta(&old).inserted.len(), 500);
+        assert_eq!(old.delta(&CompVec::default()).removed.len(), 500);
+
+        let mut new = old.clone();
+        new.remove(ids[0]);
+        new.insert(ids[1], 1);
+        *new.get_mut(ids[500]).unwrap() = 7;
+        // touched but not changed
+        *new.get_mut(ids[502]).unwrap() = 502;
+        handles.entity_deleted(ids[998]);
+        new.remove(ids[998]);
+        let reused = handles.next_handle();
+        assert_eq!(reused.index(), 998);
+        new.insert(reused, 0);
+
+        let delta = old.delta(&new);
+        assert_eq!(
+            delta,
+            CompDelta {
+                inserted: vec![(ids[1], 1), (reused, 0)],
+                removed: vec![ids[0], ids[998]],
+                changed: vec![(ids[500], 7)],
+            }
+        );
+        old.apply_delta(delta, &handles).unwrap();
+        assert_eq!(old, new);
+        assert!(old.delta(&new).is_empty());
+    }
+}
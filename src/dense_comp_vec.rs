@@ -0,0 +1,313 @@
+use crate::comp_iter::{CompIterer, NonOptionalCompIterer, Optional};
+use crate::{CompStorage, EntityHandle};
+use fixedbitset::FixedBitSet;
+
+#[derive(Debug, Clone)]
+/// A vector of components addressed directly by entity index, a
+/// `Vec<Option<(EntityHandle, T)>>` with a bitset of the present components.
+///
+/// Get, insert and remove are O(1) without counting the bitset, at the cost
+/// of a slot for every entity index. Useful for components nearly every
+/// entity has.
+pub struct DenseCompVec<T> {
+    slots: Vec<Option<(EntityHandle, T)>>,
+    owners: FixedBitSet,
+    len: usize,
+}
+
+impl<T> Default for DenseCompVec<T> {
+    fn default() -> Self {
+        Self {
+            slots: Default::default(),
+            owners: Default::default(),
+            len: 0,
+        }
+    }
+}
+
+impl<T> DenseCompVec<T> {
+    pub fn get(&self, id: EntityHandle) -> Option<&T> {
+        match self.slots.get(id.index()) {
+            Some(Some((id_out, comp))) if *id_out == id => Some(comp),
+            _ => None,
+        }
+    }
+
+    pub fn get_mut(&mut self, id: EntityHandle) -> Option<&mut T> {
+        match self.slots.get_mut(id.index()) {
+            Some(Some((id_out, comp))) if *id_out == id => Some(comp),
+            _ => None,
+        }
+    }
+
+    /// Returns the previous element if it was there
+    pub fn insert(&mut self, id: EntityHandle, comp: T) -> Option<T> {
+        if id.index() >= self.slots.len() {
+            self.slots.resize_with(id.index() + 1, || None);
+        }
+        match self.slots[id.index()].replace((id, comp)) {
+            Some((old_id, comp)) => {
+                assert_eq!(old_id, id);
+                Some(comp)
+            }
+            None => {
+                self.owners.grow_and_insert(id.index());
+                self.len += 1;
+                None
+            }
+        }
+    }
+
+    /// Returns the element if it was there, a handle of another generation removes nothing
+    pub fn remove(&mut self, id: EntityHandle) -> Option<T> {
+        let slot = self.slots.get_mut(id.index())?;
+        if slot.as_ref()?.0 != id {
+            return None;
+        }
+        let (_, comp) = slot.take()?;
+        self.owners.remove(id.index());
+        self.len -= 1;
+        Some(comp)
+    }
+
+    /// Iterator of the components in order of entity index. Can be used with `CompIter`
+    /// to iterate over components with shared ownership
+    pub fn iter(&self) -> DenseIter<'_, T> {
+        DenseIter {
+            next_entity_ind: 0,
+            slots: &self.slots,
+            owners: &self.owners,
+        }
+    }
+
+    /// Mutable Iterator of the components in order of entity index. Can be used with `CompIter`
+    /// to iterate over components with shared ownership
+    pub fn iter_mut(&mut self) -> DenseIterMut<'_, T> {
+        DenseIterMut {
+            next_entity_ind: 0,
+            slots: &mut self.slots,
+            owners: &self.owners,
+        }
+    }
+
+    pub fn owners(&self) -> &FixedBitSet {
+        &self.owners
+    }
+
+    /// Number of components
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl<T> CompStorage for DenseCompVec<T> {
+    fn owners(&self) -> &FixedBitSet {
+        &self.owners
+    }
+
+    fn remove_entity(&mut self, handle: EntityHandle) {
+        self.remove(handle);
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+/// Iterator for DenseCompVec<T>
+pub struct DenseIter<'a, T> {
+    next_entity_ind: usize,
+    /// Slots starting at `next_entity_ind`
+    slots: &'a [Option<(EntityHandle, T)>],
+    owners: &'a FixedBitSet,
+}
+
+impl<T> DenseIter<'_, T> {
+    /// Will not advance if entity_index is smaller than the last entity index
+    fn advance_forward_to(&mut self, entity_index: usize) {
+        let advance_by = entity_index.saturating_sub(self.next_entity_ind);
+        self.slots = &self.slots[advance_by..];
+        self.next_entity_ind += advance_by;
+    }
+
+    /// Make the iter optional, meaning it will not affect ownership in `CompIter`
+    /// and will return Option<T> for every set of components.
+    pub fn optional(self) -> Optional<Self> {
+        Optional(self)
+    }
+}
+
+impl<'a, T> Iterator for DenseIter<'a, T> {
+    type Item = (EntityHandle, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let [slot, rest @ ..] = self.slots {
+            self.slots = rest;
+            self.next_entity_ind += 1;
+            if let Some((id, comp)) = slot {
+                return Some((*id, comp));
+            }
+        }
+        None
+    }
+}
+
+impl<'a, T> CompIterer for DenseIter<'a, T> {
+    type Item = &'a T;
+
+    fn combine_owners(&self, owners: &mut FixedBitSet) {
+        owners.intersect_with(self.owners);
+    }
+
+    fn comp_at(&mut self, entity_handle: EntityHandle) -> Self::Item {
+        let (handle2, comp) = self.comp_at_index(entity_handle.index());
+        assert_eq!(entity_handle, handle2);
+        comp
+    }
+}
+
+impl<T> NonOptionalCompIterer for DenseIter<'_, T> {
+    fn owners(&self) -> &FixedBitSet {
+        self.owners
+    }
+
+    fn comp_at_index(&mut self, entity_index: usize) -> (EntityHandle, Self::Item) {
+        self.advance_forward_to(entity_index);
+        self.next().unwrap()
+    }
+}
+
+/// Mut iterator for DenseCompVec<T>
+pub struct DenseIterMut<'a, T> {
+    next_entity_ind: usize,
+    /// Slots starting at `next_entity_ind`
+    slots: &'a mut [Option<(EntityHandle, T)>],
+    owners: &'a FixedBitSet,
+}
+
+impl<T> DenseIterMut<'_, T> {
+    /// Will not advance if entity_index is smaller than the last entity index
+    fn advance_forward_to(&mut self, entity_index: usize) {
+        let advance_by = entity_index.saturating_sub(self.next_entity_ind);
+        let slots = std::mem::take(&mut self.slots);
+        self.slots = &mut slots[advance_by..];
+        self.next_entity_ind += advance_by;
+    }
+
+    /// Make the iter optional, meaning it will not affect ownership in `CompIter`
+    /// and will return Option<T> for every set of components.
+    pub fn optional(self) -> Optional<Self> {
+        Optional(self)
+    }
+}
+
+impl<'a, T> Iterator for DenseIterMut<'a, T> {
+    type Item = (EntityHandle, &'a mut T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let [slot, rest @ ..] = std::mem::take(&mut self.slots) {
+            self.slots = rest;
+            self.next_entity_ind += 1;
+            if let Some((id, comp)) = slot {
+                return Some((*id, comp));
+            }
+        }
+        None
+    }
+}
+
+impl<'a, T> CompIterer for DenseIterMut<'a, T> {
+    type Item = &'a mut T;
+
+    fn combine_owners(&self, owners: &mut FixedBitSet) {
+        owners.intersect_with(self.owners);
+    }
+
+    fn comp_at(&mut self, entity_handle: EntityHandle) -> Self::Item {
+        let (handle2, comp) = self.comp_at_index(entity_handle.index());
+        assert_eq!(entity_handle, handle2);
+        comp
+    }
+}
+
+impl<T> NonOptionalCompIterer for DenseIterMut<'_, T> {
+    fn owners(&self) -> &FixedBitSet {
+        self.owners
+    }
+
+    fn comp_at_index(&mut self, entity_index: usize) -> (EntityHandle, Self::Item) {
+        self.advance_forward_to(entity_index);
+        self.next().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{CompIter, CompVec, DenseCompVec, EntityHandleCounter};
+
+    #[test]
+    fn test_dense() {
+        let mut v = DenseCompVec::<usize>::default();
+        let mut other = CompVec::<bool>::default();
+        let mut handles = EntityHandleCounter::default();
+        let ids: Vec<_> = (0..10).map(|_| handles.next_handle()).collect();
+        for id in ids.iter() {
+            v.insert(*id, id.index());
+            if id.index() % 3 == 0 {
+                other.insert(*id, true);
+            }
+        }
+        assert_eq!(v.insert(ids[1], 100), Some(1));
+        assert_eq!(v.remove(ids[3]), Some(3));
+        assert_eq!(v.remove(ids[3]), None);
+        assert_eq!(v.len(), 9);
+
+        for (_id, _, comp) in CompIter::from((other.iter(), v.iter_mut())) {
+            *comp += 1000;
+        }
+        let all: Vec<_> = v.iter().map(|(id, comp)| (id.index(), *comp)).collect();
+        assert_eq!(
+            all,
+            vec![
+                (0, 1000),
+                (1, 100),
+                (2, 2),
+                (4, 4),
+                (5, 5),
+                (6, 1006),
+                (7, 7),
+                (8, 8),
+                (9, 1009)
+            ]
+        );
+
+        let joined: Vec<_> = CompIter::from((other.iter(), v.iter().optional()))
+            .into_iter()
+            .map(|(id, _, comp)| (id.index(), comp.copied()))
+            .collect();
+        assert_eq!(
+            joined,
+            vec![(0, Some(1000)), (3, None), (6, Some(1006)), (9, Some(1009))]
+        );
+    }
+
+    #[test]
+    fn test_remove_stale() {
+        let mut v = DenseCompVec::<usize>::default();
+        let mut handles = EntityHandleCounter::default();
+        let old = handles.next_handle();
+        v.insert(old, 1);
+        handles.entity_deleted(old);
+        let new = handles.next_handle();
+        assert_eq!(new.index(), old.index());
+
+        assert_eq!(v.remove(new), None);
+        assert_eq!(v.len(), 1);
+        assert_eq!(v.remove(old), Some(1));
+        assert!(v.is_empty());
+    }
+}
@@ -1,10 +1,21 @@
+use crate::SnapshotComponent;
+use crate::SnapshotError;
+use crate::snapshot::{read_bitset, write_bitset};
+use crate::{DeltaError, HandlesDelta};
+use std::io::{self, Read, Write};
+
 #[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct EntityHandle {
     index: usize,
     generation: u32,
 }
 
 impl EntityHandle {
+    pub(crate) fn new(index: usize, generation: u32) -> Self {
+        Self { index, generation }
+    }
+
     pub fn generation(&self) -> u32 {
         self.generation
     }
@@ -14,11 +25,22 @@ impl EntityHandle {
     }
 }
 
-#[derive(Default, Debug)]
+/// Gives out entity handles, reusing the indices of deleted entities with a new generation.
+///
+/// Two counters are equal if they have the same live entities and will give out
+/// the same handles, whatever the order the entities were deleted in
+#[derive(Default, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "EntityHandleCounterData"))]
 pub struct EntityHandleCounter {
     next: usize,
     generation: u32,
     removed: bool,
+    /// Freed indices, only as long as the largest one ever freed
+    #[cfg_attr(
+        feature = "serde",
+        serde(serialize_with = "crate::bitset_serde::serialize")
+    )]
     free: fixedbitset::FixedBitSet,
     generation_to_free: Vec<Option<u32>>,
 }
@@ -60,6 +82,264 @@ impl EntityHandleCounter {
             Some(generation) => generation != handle.generation(),
         }
     }
+
+    /// Handle of the live entity at `index`, if there is one
+    pub fn handle_at(&self, index: usize) -> Option<EntityHandle> {
+        let generation = self.generation_to_free.get(index).copied().flatten()?;
+        Some(EntityHandle { index, generation })
+    }
+
+    /// Generation of the live entity at every index, `None` for the free ones
+    pub(crate) fn generations(&self) -> &[Option<u32>] {
+        &self.generation_to_free
+    }
+
+    /// Bitset of the indices of the live entities
+    pub fn live_entities(&self) -> fixedbitset::FixedBitSet {
+        let mut live = fixedbitset::FixedBitSet::with_capacity(self.next);
+        live.insert_range(..);
+        live.difference_with(&self.free);
+        live
+    }
+
+    /// The entities spawned and despawned from `self` to `newer`,
+    /// with what is needed to give out the same handles as `newer` afterwards
+    pub fn delta(&self, newer: &Self) -> HandlesDelta {
+        let mut delta = HandlesDelta {
+            next: newer.next,
+            generation: newer.generation,
+            removed: newer.removed,
+            ..Default::default()
+        };
+        for index in 0..self.next.max(newer.next) {
+            let old = self.generation_to_free.get(index).copied().flatten();
+            let new = newer.generation_to_free.get(index).copied().flatten();
+            if old != new {
+                if let Some(generation) = old {
+                    delta.despawned.push(EntityHandle { index, generation });
+                }
+                if let Some(generation) = new {
+                    delta.spawned.push(EntityHandle { index, generation });
+                }
+            } else if index >= self.next {
+                delta.freed.push(index);
+            }
+        }
+        delta
+    }
+
+    /// Errors if the delta can't be applied to `self`, like a corrupted one
+    /// or one made from another state
+    pub fn check_delta(&self, delta: &HandlesDelta) -> Result<(), DeltaError> {
+        self.generations_after(delta).map(|_| ())
+    }
+
+    /// The generation of every entity index after applying the delta
+    fn generations_after(&self, delta: &HandlesDelta) -> Result<Vec<Option<u32>>, DeltaError> {
+        self.check_new_indices(delta)?;
+        let mut generations = self.generation_to_free.clone();
+        generations.resize(delta.next.max(self.next), None);
+        for handle in &delta.despawned {
+            match generations.get_mut(handle.index) {
+                Some(generation) if *generation == Some(handle.generation) => *generation = None,
+                _ => return Err(DeltaError::NotFound(*handle)),
+            }
+        }
+        for handle in &delta.spawned {
+            if handle.index >= delta.next || handle.generation > delta.generation {
+                return Err(DeltaError::InvalidHandle(*handle));
+            }
+            let generation = &mut generations[handle.index];
+            if generation.is_some() {
+                return Err(DeltaError::AlreadyPresent(*handle));
+            }
+            *generation = Some(handle.generation);
+        }
+        for (index, generation) in generations.iter().enumerate().skip(delta.next) {
+            if let Some(generation) = *generation {
+                return Err(DeltaError::InvalidHandle(EntityHandle {
+                    index,
+                    generation,
+                }));
+            }
+        }
+        generations.truncate(delta.next);
+        Ok(generations)
+    }
+
+    /// Checks every index from `self.next` to `delta.next` is listed once in the
+    /// spawned or freed ones, so `next` can't grow more than the delta has data for
+    fn check_new_indices(&self, delta: &HandlesDelta) -> Result<(), DeltaError> {
+        if let Some(index) = delta
+            .freed
+            .iter()
+            .find(|index| !(self.next..delta.next).contains(*index))
+        {
+            return Err(DeltaError::InvalidIndex(*index));
+        }
+        let spawned = delta.spawned.iter().map(|handle| handle.index);
+        let mut listed: Vec<_> = spawned
+            .filter(|index| *index >= self.next)
+            .chain(delta.freed.iter().copied())
+            .collect();
+        listed.sort_unstable();
+        let mut expected = self.next;
+        for index in listed {
+            if index < expected {
+                return Err(DeltaError::Unsorted);
+            }
+            // spawned indices past `delta.next` are `InvalidHandle`s, checked afterwards
+            if index > expected && expected < delta.next {
+                return Err(DeltaError::InvalidIndex(expected));
+            }
+            expected = index + 1;
+        }
+        if expected < delta.next {
+            return Err(DeltaError::InvalidIndex(expected));
+        }
+        Ok(())
+    }
+
+    /// Turns `self` into the newer state of the delta,
+    /// leaving `self` unchanged if `check_delta` errors
+    pub fn apply_delta(&mut self, delta: &HandlesDelta) -> Result<(), DeltaError> {
+        self.generation_to_free = self.generations_after(delta)?;
+        self.next = delta.next;
+        self.generation = delta.generation;
+        self.removed = delta.removed;
+        self.free = fixedbitset::FixedBitSet::with_capacity(self.next);
+        for (index, generation) in self.generation_to_free.iter().enumerate() {
+            if generation.is_none() {
+                self.free.insert(index);
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the counter for world snapshots: the free list,
+    /// then the generation of every entity index that isn't free
+    pub fn write_snapshot(&self, writer: &mut impl Write) -> io::Result<()> {
+        self.next.write_snapshot(writer)?;
+        self.generation.write_snapshot(writer)?;
+        self.removed.write_snapshot(writer)?;
+        write_bitset(writer, &self.free)?;
+        for generation in self.generation_to_free.iter().flatten() {
+            generation.write_snapshot(writer)?;
+        }
+        Ok(())
+    }
+
+    pub fn read_snapshot(reader: &mut impl Read) -> Result<Self, SnapshotError> {
+        let next = usize::read_snapshot(reader)?;
+        let generation = u32::read_snapshot(reader)?;
+        let removed = bool::read_snapshot(reader)?;
+        let mut free = read_bitset(reader)?;
+        if let Some(index) = free.ones().find(|index| *index >= next) {
+            return Err(SnapshotError::Invalid(format!(
+                "free index {index} was never given out"
+            )));
+        }
+        // `next` isn't trusted before the generations are read, so the free list
+        // only grows up to the indices the snapshot actually has data for
+        let mut generation_to_free = Vec::new();
+        for index in 0..next {
+            generation_to_free.push(if free.contains(index) {
+                None
+            } else {
+                let index_generation = u32::read_snapshot(reader)?;
+                if index_generation > generation {
+                    return Err(SnapshotError::Invalid(format!(
+                        "index {index} has a generation from the future"
+                    )));
+                }
+                Some(index_generation)
+            });
+        }
+        free.grow(next);
+        Ok(Self {
+            next,
+            generation,
+            removed,
+            free,
+            generation_to_free,
+        })
+    }
+}
+
+/// Unchecked fields of a deserialized `EntityHandleCounter`
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct EntityHandleCounterData {
+    next: usize,
+    generation: u32,
+    removed: bool,
+    #[serde(with = "crate::bitset_serde")]
+    free: fixedbitset::FixedBitSet,
+    generation_to_free: Vec<Option<u32>>,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<EntityHandleCounterData> for EntityHandleCounter {
+    type Error = String;
+
+    /// Checks the free list matches the freed generations
+    fn try_from(data: EntityHandleCounterData) -> Result<Self, String> {
+        if data.generation_to_free.len() != data.next {
+            return Err(format!(
+                "{} generations for {} entity indices",
+                data.generation_to_free.len(),
+                data.next
+            ));
+        }
+        if let Some(index) = data.free.ones().find(|index| *index >= data.next) {
+            return Err(format!("free index {index} was never given out"));
+        }
+        for (index, generation) in data.generation_to_free.iter().enumerate() {
+            match generation {
+                None if !data.free.contains(index) => {
+                    return Err(format!("index {index} is neither alive nor free"));
+                }
+                Some(_) if data.free.contains(index) => {
+                    return Err(format!("index {index} is both alive and free"));
+                }
+                Some(generation) if *generation > data.generation => {
+                    return Err(format!("index {index} has a generation from the future"));
+                }
+                _ => {}
+            }
+        }
+        let mut free = data.free;
+        free.grow(data.next);
+        Ok(Self {
+            next: data.next,
+            generation: data.generation,
+            removed: data.removed,
+            free,
+            generation_to_free: data.generation_to_free,
+        })
+    }
+}
+
+// `free` is left out: it's the `None`s of `generation_to_free`,
+// in a bitset whose length depends on the deletion history
+impl PartialEq for EntityHandleCounter {
+    fn eq(&self, other: &Self) -> bool {
+        self.next == other.next
+            && self.generation == other.generation
+            && self.removed == other.removed
+            && self.generation_to_free == other.generation_to_free
+    }
+}
+
+impl Eq for EntityHandleCounter {}
+
+impl std::hash::Hash for EntityHandleCounter {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.next.hash(state);
+        self.generation.hash(state);
+        self.removed.hash(state);
+        self.generation_to_free.hash(state);
+    }
 }
 
 #[cfg(test)]
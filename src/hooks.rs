@@ -0,0 +1,96 @@
+use crate::EntityHandle;
+use std::sync::Arc;
+
+type Hook<T> = Arc<dyn Fn(EntityHandle, &T) + Send + Sync>;
+
+/// Callbacks run by a `CompVec` when components are inserted, replaced or removed,
+/// see `CompVec::set_hooks`.
+///
+/// The removal hook also runs for the components removed by `retain`, `extract_if`,
+/// `remove_many` and `WorldTrait::delete_entity`.
+/// ```
+/// # use vec_ecs::{CompVec, ComponentHooks, EntityHandleCounter};
+/// # let mut handles = EntityHandleCounter::default();
+/// let mut v = CompVec::<u32>::with_hooks(
+///     ComponentHooks::default().on_remove(|handle, comp| println!("{handle:?} lost {comp}")),
+/// );
+/// let handle = handles.next_handle();
+/// v.insert(handle, 3);
+/// v.remove(handle); // prints
+/// ```
+pub struct ComponentHooks<T> {
+    on_insert: Option<Hook<T>>,
+    on_replace: Option<Hook<T>>,
+    on_remove: Option<Hook<T>>,
+}
+
+impl<T> Default for ComponentHooks<T> {
+    fn default() -> Self {
+        Self {
+            on_insert: None,
+            on_replace: None,
+            on_remove: None,
+        }
+    }
+}
+
+impl<T> Clone for ComponentHooks<T> {
+    fn clone(&self) -> Self {
+        Self {
+            on_insert: self.on_insert.clone(),
+            on_replace: self.on_replace.clone(),
+            on_remove: self.on_remove.clone(),
+        }
+    }
+}
+
+impl<T> std::fmt::Debug for ComponentHooks<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ComponentHooks")
+            .field("on_insert", &self.on_insert.is_some())
+            .field("on_replace", &self.on_replace.is_some())
+            .field("on_remove", &self.on_remove.is_some())
+            .finish()
+    }
+}
+
+impl<T> ComponentHooks<T> {
+    /// Runs with the new component when an entity that didn't have one gets it
+    #[must_use]
+    pub fn on_insert(mut self, hook: impl Fn(EntityHandle, &T) + Send + Sync + 'static) -> Self {
+        self.on_insert = Some(Arc::new(hook));
+        self
+    }
+
+    /// Runs with the previous component when it is replaced by an insert
+    #[must_use]
+    pub fn on_replace(mut self, hook: impl Fn(EntityHandle, &T) + Send + Sync + 'static) -> Self {
+        self.on_replace = Some(Arc::new(hook));
+        self
+    }
+
+    /// Runs with the component when it is removed
+    #[must_use]
+    pub fn on_remove(mut self, hook: impl Fn(EntityHandle, &T) + Send + Sync + 'static) -> Self {
+        self.on_remove = Some(Arc::new(hook));
+        self
+    }
+
+    pub(crate) fn inserted(&self, id: EntityHandle, comp: &T) {
+        if let Some(hook) = &self.on_insert {
+            hook(id, comp);
+        }
+    }
+
+    pub(crate) fn replaced(&self, id: EntityHandle, comp: &T) {
+        if let Some(hook) = &self.on_replace {
+            hook(id, comp);
+        }
+    }
+
+    pub(crate) fn removed(&self, id: EntityHandle, comp: &T) {
+        if let Some(hook) = &self.on_remove {
+            hook(id, comp);
+        }
+    }
+}
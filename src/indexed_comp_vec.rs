@@ -0,0 +1,343 @@
+use crate::comp_iter::{CompIterer, Iter, IterMut, NonOptionalCompIterer, Optional};
+use crate::{CompStorage, CompVec, EntityHandle};
+use fixedbitset::FixedBitSet;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::ops::{Deref, DerefMut};
+use std::rc::Rc;
+
+type Index<T> = HashMap<T, Vec<EntityHandle>>;
+
+#[derive(Debug, Clone)]
+/// A `CompVec` that also keeps a map from every component value to the
+/// entities having it, to find entities by `Name`, `NetId`, ... without
+/// scanning all the components.
+///
+/// Components can only be mutated through `IndexedMut` guards, which
+/// update the map when dropped.
+pub struct IndexedCompVec<T> {
+    comps: CompVec<T>,
+    index: Index<T>,
+}
+
+impl<T> Default for IndexedCompVec<T> {
+    fn default() -> Self {
+        Self {
+            comps: Default::default(),
+            index: Default::default(),
+        }
+    }
+}
+
+fn index_insert<T: Hash + Eq + Clone>(index: &mut Index<T>, id: EntityHandle, comp: &T) {
+    match index.get_mut(comp) {
+        Some(handles) => handles.push(id),
+        None => {
+            index.insert(comp.clone(), vec![id]);
+        }
+    }
+}
+
+fn index_remove<T: Hash + Eq>(index: &mut Index<T>, id: EntityHandle, comp: &T) {
+    if let Some(handles) = index.get_mut(comp) {
+        if let Some(pos) = handles.iter().position(|handle| *handle == id) {
+            handles.swap_remove(pos);
+        }
+        if handles.is_empty() {
+            index.remove(comp);
+        }
+    }
+}
+
+impl<T: Hash + Eq + Clone> IndexedCompVec<T> {
+    pub fn get(&self, id: EntityHandle) -> Option<&T> {
+        self.comps.get(id)
+    }
+
+    /// The component is re-indexed when the guard is dropped
+    pub fn get_mut(&mut self, id: EntityHandle) -> Option<IndexedMut<'_, T>> {
+        let comp = self.comps.get_mut(id)?;
+        index_remove(&mut self.index, id, comp);
+        Some(IndexedMut {
+            id,
+            comp,
+            index: IndexRef::Unique(&mut self.index),
+        })
+    }
+
+    /// Returns the previous element if it was there
+    pub fn insert(&mut self, id: EntityHandle, comp: T) -> Option<T> {
+        index_insert(&mut self.index, id, &comp);
+        let old = self.comps.insert(id, comp)?;
+        index_remove(&mut self.index, id, &old);
+        Some(old)
+    }
+
+    /// Returns the element if it was there
+    pub fn remove(&mut self, id: EntityHandle) -> Option<T> {
+        let comp = self.comps.remove(id)?;
+        index_remove(&mut self.index, id, &comp);
+        Some(comp)
+    }
+
+    /// Only keeps the components for which `keep` returns true
+    pub fn retain(&mut self, mut keep: impl FnMut(EntityHandle, &T) -> bool) {
+        self.comps.retain(|id, comp| {
+            let kept = keep(id, comp);
+            if !kept {
+                index_remove(&mut self.index, id, comp);
+            }
+            kept
+        });
+    }
+
+    /// The entities whose component equals `value`, in no particular order
+    pub fn handles_with(&self, value: &T) -> &[EntityHandle] {
+        self.index.get(value).map_or(&[], Vec::as_slice)
+    }
+
+    /// Whether any entity has a component equal to `value`
+    pub fn contains_value(&self, value: &T) -> bool {
+        self.index.contains_key(value)
+    }
+
+    /// Iterator of the components in order of entity index. Can be used with `CompIter`
+    /// to iterate over components with shared ownership
+    pub fn iter(&self) -> Iter<'_, T> {
+        self.comps.iter()
+    }
+
+    /// Mutable iterator of the components in order of entity index, every component
+    /// is handed out as a guard re-indexing it when dropped. Can be used with `CompIter`.
+    ///
+    /// Prefer `iter` when not modifying the components, since every guard looks up the index twice
+    pub fn iter_mut(&mut self) -> IndexedIterMut<'_, T> {
+        IndexedIterMut {
+            inner: self.comps.iter_mut(),
+            index: Rc::new(RefCell::new(&mut self.index)),
+        }
+    }
+
+    /// See `CompVec::is_dirty`
+    pub fn is_dirty(&self) -> bool {
+        self.comps.is_dirty()
+    }
+
+    pub fn clear_dirty(&mut self) {
+        self.comps.clear_dirty();
+    }
+
+    /// The underlying `CompVec`, for read-only access
+    pub fn comps(&self) -> &CompVec<T> {
+        &self.comps
+    }
+
+    pub fn owners(&self) -> &FixedBitSet {
+        self.comps.owners()
+    }
+
+    /// Number of components
+    pub fn len(&self) -> usize {
+        self.comps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.comps.is_empty()
+    }
+}
+
+/// Only compares the components, the map follows from them
+impl<T: PartialEq> PartialEq for IndexedCompVec<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.comps == other.comps
+    }
+}
+
+impl<T: Eq> Eq for IndexedCompVec<T> {}
+
+impl<T: Hash> Hash for IndexedCompVec<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.comps.hash(state);
+    }
+}
+
+/// Serialized as the underlying `CompVec`
+#[cfg(feature = "serde")]
+impl<T: serde::Serialize> serde::Serialize for IndexedCompVec<T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.comps.serialize(serializer)
+    }
+}
+
+/// Rebuilds the index from the deserialized `CompVec`
+#[cfg(feature = "serde")]
+impl<'de, T: serde::Deserialize<'de> + Hash + Eq + Clone> serde::Deserialize<'de>
+    for IndexedCompVec<T>
+{
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let comps = CompVec::<T>::deserialize(deserializer)?;
+        let mut index = Index::default();
+        for (id, comp) in comps.iter() {
+            index_insert(&mut index, id, comp);
+        }
+        Ok(Self { comps, index })
+    }
+}
+
+impl<T: Hash + Eq + Clone> CompStorage for IndexedCompVec<T> {
+    fn owners(&self) -> &FixedBitSet {
+        self.comps.owners()
+    }
+
+    fn remove_entity(&mut self, handle: EntityHandle) {
+        self.remove(handle);
+    }
+
+    fn is_empty(&self) -> bool {
+        self.comps.is_empty()
+    }
+}
+
+enum IndexRef<'a, T> {
+    Unique(&'a mut Index<T>),
+    /// Shared by all the guards of an `IndexedIterMut`
+    Shared(Rc<RefCell<&'a mut Index<T>>>),
+}
+
+/// Mutable reference to a component of an `IndexedCompVec`,
+/// re-indexing the component when dropped
+pub struct IndexedMut<'a, T: Hash + Eq + Clone> {
+    id: EntityHandle,
+    comp: &'a mut T,
+    index: IndexRef<'a, T>,
+}
+
+impl<T: Hash + Eq + Clone> IndexedMut<'_, T> {
+    pub fn handle(&self) -> EntityHandle {
+        self.id
+    }
+}
+
+impl<T: Hash + Eq + Clone> Deref for IndexedMut<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.comp
+    }
+}
+
+impl<T: Hash + Eq + Clone> DerefMut for IndexedMut<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.comp
+    }
+}
+
+impl<T: Hash + Eq + Clone> Drop for IndexedMut<'_, T> {
+    fn drop(&mut self) {
+        match &mut self.index {
+            IndexRef::Unique(index) => index_insert(index, self.id, self.comp),
+            IndexRef::Shared(index) => index_insert(&mut index.borrow_mut(), self.id, self.comp),
+        }
+    }
+}
+
+impl<T: Hash + Eq + Clone + std::fmt::Debug> std::fmt::Debug for IndexedMut<'_, T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.comp.fmt(f)
+    }
+}
+
+/// Mut iterator for IndexedCompVec<T>
+pub struct IndexedIterMut<'a, T> {
+    inner: IterMut<'a, T>,
+    index: Rc<RefCell<&'a mut Index<T>>>,
+}
+
+impl<'a, T: Hash + Eq + Clone> IndexedIterMut<'a, T> {
+    fn guard(&self, id: EntityHandle, comp: &'a mut T) -> IndexedMut<'a, T> {
+        index_remove(&mut self.index.borrow_mut(), id, comp);
+        IndexedMut {
+            id,
+            comp,
+            index: IndexRef::Shared(self.index.clone()),
+        }
+    }
+
+    /// Make the iter optional, meaning it will not affect ownership in `CompIter`
+    /// and will return Option<T> for every set of components.
+    pub fn optional(self) -> Optional<Self> {
+        Optional(self)
+    }
+}
+
+impl<'a, T: Hash + Eq + Clone> Iterator for IndexedIterMut<'a, T> {
+    type Item = (EntityHandle, IndexedMut<'a, T>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (id, comp) = self.inner.next()?;
+        Some((id, self.guard(id, comp)))
+    }
+}
+
+impl<'a, T: Hash + Eq + Clone> CompIterer for IndexedIterMut<'a, T> {
+    type Item = IndexedMut<'a, T>;
+
+    fn combine_owners(&self, owners: &mut FixedBitSet) {
+        self.inner.combine_owners(owners);
+    }
+
+    fn comp_at(&mut self, entity_handle: EntityHandle) -> Self::Item {
+        let comp = self.inner.comp_at(entity_handle);
+        self.guard(entity_handle, comp)
+    }
+}
+
+impl<T: Hash + Eq + Clone> NonOptionalCompIterer for IndexedIterMut<'_, T> {
+    fn owners(&self) -> &FixedBitSet {
+        self.inner.owners()
+    }
+
+    fn comp_at_index(&mut self, entity_index: usize) -> (EntityHandle, Self::Item) {
+        let (id, comp) = self.inner.comp_at_index(entity_index);
+        (id, self.guard(id, comp))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{CompIter, CompVec, EntityHandleCounter, IndexedCompVec};
+
+    #[test]
+    fn test_indexed() {
+        let mut cells = IndexedCompVec::<(i32, i32)>::default();
+        let mut vel = CompVec::<i32>::default();
+        let mut handles = EntityHandleCounter::default();
+        let ids: Vec<_> = (0..100).map(|_| handles.next_handle()).collect();
+        for (i, id) in ids.iter().enumerate() {
+            cells.insert(*id, (i as i32 % 10, 0));
+            if i % 2 == 0 {
+                vel.insert(*id, 1);
+            }
+        }
+        assert_eq!(cells.handles_with(&(3, 0)).len(), 10);
+
+        *cells.get_mut(ids[3]).unwrap() = (-1, -1);
+        assert_eq!(cells.handles_with(&(-1, -1)), &[ids[3]]);
+        assert_eq!(cells.handles_with(&(3, 0)).len(), 9);
+
+        for (_id, mut cell, vel) in CompIter::from((cells.iter_mut(), vel.iter())) {
+            cell.1 += vel;
+        }
+        assert_eq!(cells.handles_with(&(2, 0)).len(), 0);
+        assert_eq!(cells.handles_with(&(2, 1)).len(), 10);
+        assert_eq!(cells.handles_with(&(3, 0)).len(), 9);
+
+        cells.insert(ids[13], (-1, -1));
+        cells.remove(ids[3]);
+        cells.retain(|id, _| id.index() != 23);
+        assert_eq!(cells.handles_with(&(-1, -1)), &[ids[13]]);
+        assert_eq!(cells.handles_with(&(3, 0)).len(), 7);
+        assert!(!cells.contains_value(&(0, 5)));
+    }
+}
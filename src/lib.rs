@@ -1,12 +1,42 @@
+#[cfg(feature = "serde")]
+mod bitset_serde;
 pub mod comp_iter;
 mod comp_vec;
+mod delta;
+mod dense_comp_vec;
 mod entity;
 mod entity_handle;
+mod hooks;
+mod indexed_comp_vec;
+mod query;
+mod rank;
+mod rollback;
+mod snapshot;
+mod sparse_comp_vec;
+mod storage;
+mod tag_vec;
+mod unique_comp_vec;
 mod world;
 
-pub use comp_iter::CompIter;
+pub use comp_iter::{CompIter, Entities};
 pub use comp_vec::*;
+pub use delta::*;
+pub use dense_comp_vec::*;
 pub use entity::*;
 pub use entity_handle::*;
+pub use hooks::*;
+pub use indexed_comp_vec::*;
+pub use query::*;
+pub use rollback::*;
+pub use snapshot::*;
+pub use sparse_comp_vec::*;
+pub use storage::*;
+pub use tag_vec::*;
+pub use unique_comp_vec::*;
 pub use vec_ecs_macro::*;
 pub use world::*;
+
+/// Used by the serde impls of `#[world(derive(Serialize, Deserialize))]`,
+/// and by worlds deriving them with `#[serde(crate = "vec_ecs::serde")]`
+#[cfg(feature = "serde")]
+pub use serde;
@@ -0,0 +1,142 @@
+use crate::comp_iter::{CompIterer, Iter, IterMut, NonOptionalCompIterer, Optional};
+use crate::{CompIter, CompVec, EntityHandle};
+
+/// Struct of named components to iterate over, implemented by `#[derive(Query)]`
+/// for the worlds and borrow structs in its `#[query(...)]` attributes,
+/// and for tuples of `QueryItem`s on the worlds implementing `HasComp`
+pub trait QueryFromWorldTrait<'a, WorldType>: Sized {
+    /// Iterates over the entities having all the non-optional components of the query
+    fn query(world: &'a mut WorldType) -> impl Iterator<Item = (EntityHandle, Self)>;
+}
+
+/// Queries on a world or borrow struct, implemented by `#[derive(World)]`
+pub trait WorldQueryTrait {
+    /// The `CompVec`s of the world that `HasComp` is implemented for,
+    /// to borrow several of them at once
+    type Split<'a>
+    where
+        Self: 'a;
+
+    fn split_comps(&mut self) -> Self::Split<'_>;
+
+    /// Iterates over the entities matching a `#[derive(Query)]` struct,
+    /// or a tuple like `(&mut Position, &Velocity, Option<&Flag>)`.
+    ///
+    /// Panics if the tuple lists a component type twice, like `(&mut Position, &Position)`,
+    /// as the `CompVec` can only be borrowed once
+    fn query<'a, Q: QueryFromWorldTrait<'a, Self>>(
+        &'a mut self,
+    ) -> impl Iterator<Item = (EntityHandle, Q)>
+    where
+        Self: Sized,
+    {
+        Q::query(self)
+    }
+}
+
+/// A world or borrow struct with a `CompVec<T>` field. Implemented by `#[derive(World)]`
+/// for every `CompVec<T>` field whose component type isn't in another `CompVec` field.
+/// The fields are compared as written, so `Position` and `crate::Position` give
+/// conflicting impls unless one of them is labelled `#[world(no_has_comp)]`.
+///
+/// Functions taking `&mut (impl HasComp<Position> + HasComp<Velocity>)` work on the world
+/// and all its `split_*` borrows having the components
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` has no `HasComp<{T}>`",
+    note = "`HasComp` is only implemented for the component types in a single `CompVec` field of the world, \
+            use the fields directly for the types in several of them"
+)]
+pub trait HasComp<T>: WorldQueryTrait {
+    fn comps(&self) -> &CompVec<T>;
+
+    fn comps_mut(&mut self) -> &mut CompVec<T>;
+
+    /// Takes the `CompVec<T>` out of the split world, so tuple queries can borrow
+    /// several `CompVec`s at once. Panics if it was already taken, which happens
+    /// when a query lists `T` twice
+    fn take_comps<'a>(split: &mut Self::Split<'a>) -> &'a mut CompVec<T>
+    where
+        Self: 'a;
+}
+
+/// An element of a tuple query: `&T`, `&mut T`, `Option<&T>` or `Option<&mut T>`
+pub trait QueryItem<'a>: Sized {
+    type Comp: 'a;
+    type Iter: CompIterer<Item = Self>;
+
+    fn iter(comps: &'a mut CompVec<Self::Comp>) -> Self::Iter;
+}
+
+impl<'a, T> QueryItem<'a> for &'a T {
+    type Comp = T;
+    type Iter = Iter<'a, T>;
+
+    fn iter(comps: &'a mut CompVec<T>) -> Iter<'a, T> {
+        comps.iter()
+    }
+}
+
+impl<'a, T> QueryItem<'a> for &'a mut T {
+    type Comp = T;
+    type Iter = IterMut<'a, T>;
+
+    fn iter(comps: &'a mut CompVec<T>) -> IterMut<'a, T> {
+        comps.iter_mut()
+    }
+}
+
+impl<'a, T> QueryItem<'a> for Option<&'a T> {
+    type Comp = T;
+    type Iter = Optional<Iter<'a, T>>;
+
+    fn iter(comps: &'a mut CompVec<T>) -> Optional<Iter<'a, T>> {
+        comps.iter().optional()
+    }
+}
+
+impl<'a, T> QueryItem<'a> for Option<&'a mut T> {
+    type Comp = T;
+    type Iter = Optional<IterMut<'a, T>>;
+
+    fn iter(comps: &'a mut CompVec<T>) -> Optional<IterMut<'a, T>> {
+        comps.iter_mut().optional()
+    }
+}
+
+macro_rules! impl_query {
+    ($($generics:ident),* ; $($names:ident),*) => {
+        impl<'a, W, Q1: QueryItem<'a>, $($generics: QueryItem<'a>, )*> QueryFromWorldTrait<'a, W>
+            for (Q1, $($generics,)*)
+        where
+            W: HasComp<Q1::Comp> $(+ HasComp<$generics::Comp>)*,
+            Q1::Iter: NonOptionalCompIterer,
+        {
+            fn query(world: &'a mut W) -> impl Iterator<Item = (EntityHandle, Self)> {
+                let mut split = world.split_comps();
+                let comp1 = Q1::iter(<W as HasComp<Q1::Comp>>::take_comps(&mut split));
+                $(
+                    let $names = $generics::iter(<W as HasComp<$generics::Comp>>::take_comps(&mut split));
+                )*
+                CompIter::from((comp1, $($names,)*))
+                    .into_iter()
+                    .map(|(id, comp1, $($names,)*)| (id, (comp1, $($names,)*)))
+            }
+        }
+    };
+}
+impl_query!(;);
+impl_query!(Q2; comp2);
+impl_query!(Q2, Q3; comp2, comp3);
+impl_query!(Q2, Q3, Q4; comp2, comp3, comp4);
+impl_query!(Q2, Q3, Q4, Q5; comp2, comp3, comp4, comp5);
+impl_query!(Q2, Q3, Q4, Q5, Q6; comp2, comp3, comp4, comp5, comp6);
+impl_query!(Q2, Q3, Q4, Q5, Q6, Q7; comp2, comp3, comp4, comp5, comp6, comp7);
+impl_query!(Q2, Q3, Q4, Q5, Q6, Q7, Q8; comp2, comp3, comp4, comp5, comp6, comp7, comp8);
+impl_query!(Q2, Q3, Q4, Q5, Q6, Q7, Q8, Q9; comp2, comp3, comp4, comp5, comp6, comp7, comp8, comp9);
+impl_query!(Q2, Q3, Q4, Q5, Q6, Q7, Q8, Q9, Q10; comp2, comp3, comp4, comp5, comp6, comp7, comp8, comp9, comp10);
+impl_query!(Q2, Q3, Q4, Q5, Q6, Q7, Q8, Q9, Q10, Q11; comp2, comp3, comp4, comp5, comp6, comp7, comp8, comp9, comp10, comp11);
+impl_query!(Q2, Q3, Q4, Q5, Q6, Q7, Q8, Q9, Q10, Q11, Q12; comp2, comp3, comp4, comp5, comp6, comp7, comp8, comp9, comp10, comp11, comp12);
+impl_query!(Q2, Q3, Q4, Q5, Q6, Q7, Q8, Q9, Q10, Q11, Q12, Q13; comp2, comp3, comp4, comp5, comp6, comp7, comp8, comp9, comp10, comp11, comp12, comp13);
+impl_query!(Q2, Q3, Q4, Q5, Q6, Q7, Q8, Q9, Q10, Q11, Q12, Q13, Q14; comp2, comp3, comp4, comp5, comp6, comp7, comp8, comp9, comp10, comp11, comp12, comp13, comp14);
+impl_query!(Q2, Q3, Q4, Q5, Q6, Q7, Q8, Q9, Q10, Q11, Q12, Q13, Q14, Q15; comp2, comp3, comp4, comp5, comp6, comp7, comp8, comp9, comp10, comp11, comp12, comp13, comp14, comp15);
+impl_query!(Q2, Q3, Q4, Q5, Q6, Q7, Q8, Q9, Q10, Q11, Q12, Q13, Q14, Q15, Q16; comp2, comp3, comp4, comp5, comp6, comp7, comp8, comp9, comp10, comp11, comp12, comp13, comp14, comp15, comp16);
@@ -0,0 +1,124 @@
+/// Rank index over the pages of a `CompVec`.
+///
+/// A Fenwick tree of the number of components in every page, so the
+/// number of components before a page can be found in O(log n), and a
+/// component index in the whole `CompVec` can be turned back into a page
+/// and a position in that page in O(log n).
+#[derive(Debug, Default, Clone)]
+pub(crate) struct RankIndex {
+    /// 1-based Fenwick tree, `tree[0]` is unused. The number of blocks
+    /// covered is always a power of two
+    tree: Vec<usize>,
+}
+
+impl RankIndex {
+    /// Number of elements in the blocks `0..block`
+    pub(crate) fn rank(&self, block: usize) -> usize {
+        let mut i = block.min(self.capacity());
+        let mut count = 0;
+        while i > 0 {
+            count += self.tree[i];
+            i &= i - 1;
+        }
+        count
+    }
+
+    /// Total number of elements in all blocks
+    pub(crate) fn total(&self) -> usize {
+        self.rank(self.capacity())
+    }
+
+    /// Finds the block containing the `rank`th element, and the position
+    /// of the element in the block. Returns `None` if there are not that
+    /// many elements
+    pub(crate) fn select(&self, mut rank: usize) -> Option<(usize, usize)> {
+        let mut block = 0;
+        let mut step = self.capacity();
+        while step > 0 {
+            if block + step <= self.capacity() && self.tree[block + step] <= rank {
+                block += step;
+                rank -= self.tree[block];
+            }
+            step /= 2;
+        }
+        (block < self.capacity()).then_some((block, rank))
+    }
+
+    /// Adds `count` elements to `block`
+    pub(crate) fn inserted(&mut self, block: usize, count: usize) {
+        self.grow(block + 1);
+        let mut i = block + 1;
+        while i < self.tree.len() {
+            self.tree[i] += count;
+            i += i & i.wrapping_neg();
+        }
+    }
+
+    /// Removes `count` elements from `block`
+    pub(crate) fn removed(&mut self, block: usize, count: usize) {
+        let mut i = block + 1;
+        while i < self.tree.len() {
+            self.tree[i] -= count;
+            i += i & i.wrapping_neg();
+        }
+    }
+
+    /// Makes the tree cover at least `blocks` blocks.
+    ///
+    /// Doubling a Fenwick tree only needs the new root to hold the total
+    /// of the old tree, every other new node covers empty blocks
+    fn grow(&mut self, blocks: usize) {
+        if self.tree.is_empty() {
+            self.tree.resize(2, 0);
+        }
+        while self.capacity() < blocks {
+            let capacity = self.capacity();
+            let total = self.tree[capacity];
+            self.tree.resize(capacity * 2 + 1, 0);
+            self.tree[capacity * 2] = total;
+        }
+    }
+
+    /// Number of blocks covered by the tree
+    fn capacity(&self) -> usize {
+        self.tree.len().saturating_sub(1)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::RankIndex;
+
+    #[test]
+    fn test_rank_and_select() {
+        let mut counts = vec![0usize; 100];
+        let mut rank = RankIndex::default();
+
+        for i in 0..1000 {
+            let block = (i * 37) % counts.len();
+            counts[block] += 1;
+            rank.inserted(block, 1);
+        }
+        for i in 0..300 {
+            let block = (i * 11) % counts.len();
+            if counts[block] > 0 {
+                counts[block] -= 1;
+                rank.removed(block, 1);
+            }
+        }
+
+        for block in 0..=counts.len() {
+            assert_eq!(rank.rank(block), counts[..block].iter().sum::<usize>());
+        }
+        assert_eq!(rank.total(), counts.iter().sum::<usize>());
+
+        let mut expected = counts
+            .iter()
+            .enumerate()
+            .flat_map(|(block, count)| (0..*count).map(move |pos| (block, pos)));
+        for i in 0..rank.total() {
+            assert_eq!(rank.select(i), expected.next());
+        }
+        assert_eq!(rank.select(rank.total()), None);
+    }
+}
@@ -0,0 +1,237 @@
+use crate::{CompVec, DenseCompVec, IndexedCompVec, SparseCompVec, TagVec, UniqueCompVec};
+use std::collections::VecDeque;
+use std::hash::Hash;
+use std::sync::Arc;
+
+/// A component storage that can be saved in a `RollbackBuffer`.
+///
+/// Storages without a dirty flag are copied on every save
+pub trait RollbackStorage: Clone {
+    /// Whether the storage changed since the last `clear_dirty`
+    fn is_dirty(&self) -> bool {
+        true
+    }
+
+    fn clear_dirty(&mut self) {}
+
+    /// Copy of the storage, sharing the copy of `base` if the storage
+    /// didn't change since it was saved or restored
+    fn save_copy(&mut self, base: Option<&Arc<Self>>) -> Arc<Self> {
+        let copy = match base {
+            Some(base) if !self.is_dirty() => base.clone(),
+            _ => Arc::new(self.clone()),
+        };
+        self.clear_dirty();
+        copy
+    }
+
+    /// Sets the storage back to `saved`, skipping the copy if the storage
+    /// didn't change since `base`, which is `saved`
+    fn restore_copy(&mut self, saved: &Arc<Self>, base: Option<&Arc<Self>>) {
+        let unchanged = base.is_some_and(|base| Arc::ptr_eq(base, saved)) && !self.is_dirty();
+        if !unchanged {
+            self.restore_from(saved);
+        }
+        self.clear_dirty();
+    }
+
+    /// Sets the storage back to `saved`
+    fn restore_from(&mut self, saved: &Self) {
+        self.clone_from(saved);
+    }
+}
+
+impl<T: Clone> RollbackStorage for CompVec<T> {
+    fn is_dirty(&self) -> bool {
+        CompVec::is_dirty(self)
+    }
+
+    fn clear_dirty(&mut self) {
+        CompVec::clear_dirty(self);
+    }
+
+    /// Only restores the components, the hooks, tracking and undrained
+    /// added/removed components are those of the live storage
+    fn restore_from(&mut self, saved: &Self) {
+        self.restore_comps(saved);
+    }
+}
+
+impl<T: Hash + Eq + Clone> RollbackStorage for IndexedCompVec<T> {
+    fn is_dirty(&self) -> bool {
+        IndexedCompVec::is_dirty(self)
+    }
+
+    fn clear_dirty(&mut self) {
+        IndexedCompVec::clear_dirty(self);
+    }
+}
+
+impl<T: Hash + Eq + Clone> RollbackStorage for UniqueCompVec<T> {
+    fn is_dirty(&self) -> bool {
+        UniqueCompVec::is_dirty(self)
+    }
+
+    fn clear_dirty(&mut self) {
+        UniqueCompVec::clear_dirty(self);
+    }
+}
+
+impl<T: Clone> RollbackStorage for SparseCompVec<T> {}
+
+impl<T: Clone> RollbackStorage for DenseCompVec<T> {}
+
+impl RollbackStorage for TagVec {}
+
+/// A world whose state can be saved in a `RollbackBuffer`,
+/// implemented by `#[world(rollback)]`
+pub trait RollbackWorld {
+    /// Saved state of the world, sharing the storages that didn't change
+    /// with the previous frames
+    type Frame: Clone;
+
+    /// Saves the world, `base` is the frame it was last saved to or restored from
+    fn save_frame(&mut self, base: Option<&Self::Frame>) -> Self::Frame;
+
+    /// Sets the world back to `frame`, `base` is the frame it was last saved
+    /// to or restored from
+    fn restore_frame(&mut self, frame: &Self::Frame, base: Option<&Self::Frame>);
+}
+
+/// Ring buffer of the last world states, for rollback netcode.
+///
+/// Saving only copies the storages that changed since the previous save or
+/// restore, the others are shared between frames. The `EntityHandleCounter`
+/// is always copied, so re-simulated spawns get the same handles.
+/// The world needs `#[world(rollback)]`
+pub struct RollbackBuffer<W: RollbackWorld> {
+    capacity: usize,
+    frames: VecDeque<(u64, W::Frame)>,
+    /// Frame the world was last saved to or restored from
+    base: Option<W::Frame>,
+}
+
+impl<W: RollbackWorld> RollbackBuffer<W> {
+    /// Buffer keeping the states of the last `capacity` saved ticks
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "a RollbackBuffer needs a capacity");
+        Self {
+            capacity,
+            frames: VecDeque::with_capacity(capacity),
+            base: None,
+        }
+    }
+
+    /// Saves the state of the world at `tick`. The states saved at `tick` or after
+    /// are replaced, and the oldest one is dropped if the buffer is full
+    pub fn save(&mut self, world: &mut W, tick: u64) {
+        let frame = world.save_frame(self.base.as_ref());
+        self.base = Some(frame.clone());
+        while self.frames.back().is_some_and(|(saved, _)| *saved >= tick) {
+            self.frames.pop_back();
+        }
+        self.frames.push_back((tick, frame));
+        if self.frames.len() > self.capacity {
+            self.frames.pop_front();
+        }
+    }
+
+    /// Sets the world back to its state at `tick`, returns false if
+    /// there is none. The states saved after `tick` are kept until overwritten
+    pub fn restore(&mut self, world: &mut W, tick: u64) -> bool {
+        let Some((_, frame)) = self.frames.iter().find(|(saved, _)| *saved == tick) else {
+            return false;
+        };
+        let frame = frame.clone();
+        world.restore_frame(&frame, self.base.as_ref());
+        self.base = Some(frame);
+        true
+    }
+
+    /// Drops the states saved before `tick`, for ticks that can't be rolled back to anymore
+    pub fn discard_before(&mut self, tick: u64) {
+        while self.frames.front().is_some_and(|(saved, _)| *saved < tick) {
+            self.frames.pop_front();
+        }
+    }
+
+    /// Whether a state was saved at `tick`
+    pub fn contains(&self, tick: u64) -> bool {
+        self.frames.iter().any(|(saved, _)| *saved == tick)
+    }
+
+    /// The saved ticks, from oldest to newest
+    pub fn ticks(&self) -> impl Iterator<Item = u64> + '_ {
+        self.frames.iter().map(|(tick, _)| *tick)
+    }
+
+    /// Number of saved states
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{CompVec, ComponentHooks, EntityHandleCounter, RollbackStorage, Tracking};
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn test_save_copy() {
+        let mut handles = EntityHandleCounter::default();
+        let id = handles.next_handle();
+        let mut pos = CompVec::<i32>::default();
+        pos.insert(id, 0);
+        let first = pos.save_copy(None);
+        assert!(!pos.is_dirty());
+        assert!(Arc::ptr_eq(&first, &pos.save_copy(Some(&first))));
+
+        pos.get(id);
+        assert!(Arc::ptr_eq(&first, &pos.save_copy(Some(&first))));
+        *pos.get_mut(id).unwrap() = 1;
+        let second = pos.save_copy(Some(&first));
+        assert!(!Arc::ptr_eq(&first, &second));
+
+        pos.remove(id);
+        assert!(pos.is_dirty());
+        pos.restore_copy(&first, Some(&second));
+        assert_eq!(pos.get(id), Some(&0));
+        assert!(!pos.is_dirty());
+    }
+
+    #[test]
+    fn test_restore_keeps_hooks_and_tracking() {
+        let mut handles = EntityHandleCounter::default();
+        let (a, b) = (handles.next_handle(), handles.next_handle());
+        let mut pos = CompVec::<i32>::default();
+        pos.set_tracking(Tracking::Handles);
+        pos.insert(a, 0);
+        let saved = pos.save_copy(None);
+        assert_eq!(pos.drain_added().collect::<Vec<_>>(), vec![a]);
+
+        let inserted = Arc::new(AtomicUsize::new(0));
+        let counter = inserted.clone();
+        pos.set_hooks(ComponentHooks::default().on_insert(move |_, _| {
+            counter.fetch_add(1, Ordering::Relaxed);
+        }));
+        pos.insert(b, 1);
+        pos.restore_copy(&saved, Some(&saved));
+        assert_eq!(pos.get(a), Some(&0));
+        assert_eq!(pos.get(b), None);
+
+        // the drained insertion doesn't come back, the one after the save isn't dropped
+        assert_eq!(pos.drain_added().collect::<Vec<_>>(), vec![b]);
+        pos.insert(b, 2);
+        assert_eq!(inserted.load(Ordering::Relaxed), 2);
+        assert_eq!(pos.drain_added().collect::<Vec<_>>(), vec![b]);
+    }
+}
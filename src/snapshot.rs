@@ -0,0 +1,661 @@
+use crate::{
+    CompVec, DenseCompVec, EntityHandle, IndexedCompVec, SparseCompVec, TagVec, UniqueCompVec,
+};
+use fixedbitset::FixedBitSet;
+use std::hash::Hash;
+use std::io::{self, Read, Write};
+
+/// Version of the snapshot format written by `#[world(snapshot)]` worlds
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+const MAGIC: [u8; 4] = *b"VECS";
+
+/// Error reading a world snapshot
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(io::Error),
+    /// The data doesn't start like a snapshot
+    BadMagic,
+    /// The snapshot was written with another version of the format
+    UnsupportedVersion(u32),
+    /// The fields of the snapshot don't match the fields of the world
+    SchemaMismatch(String),
+    /// The snapshot is inconsistent, like a component without owner
+    Invalid(String),
+}
+
+impl std::fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SnapshotError::Io(e) => write!(f, "snapshot io error: {e}"),
+            SnapshotError::BadMagic => write!(f, "not a world snapshot"),
+            SnapshotError::UnsupportedVersion(version) => write!(
+                f,
+                "snapshot format version {version} is not supported, expected {SNAPSHOT_VERSION}"
+            ),
+            SnapshotError::SchemaMismatch(msg) => write!(f, "snapshot schema mismatch: {msg}"),
+            SnapshotError::Invalid(msg) => write!(f, "invalid snapshot: {msg}"),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SnapshotError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for SnapshotError {
+    fn from(e: io::Error) -> Self {
+        SnapshotError::Io(e)
+    }
+}
+
+/// FNV-1a, used for the type hashes since it doesn't change between builds.
+/// The hashes are only as stable as what is fed to it, so hash fixed names
+/// rather than `std::any::type_name`, which can change between compiler versions
+pub struct TypeHasher(u64);
+
+impl Default for TypeHasher {
+    fn default() -> Self {
+        Self(0xcbf29ce484222325)
+    }
+}
+
+impl TypeHasher {
+    #[must_use]
+    pub fn bytes(mut self, bytes: &[u8]) -> Self {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+        self
+    }
+
+    #[must_use]
+    pub fn hash(self, hash: u64) -> Self {
+        self.bytes(&hash.to_le_bytes())
+    }
+
+    pub fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+/// Byte encoding of a component in world snapshots.
+///
+/// Implemented for primitives, `String`, `Vec`, `Option`, arrays and tuples,
+/// component types can encode themselves with their fields
+/// ```
+/// # use vec_ecs::{SnapshotComponent, TypeHasher};
+/// # use std::io::{self, Read, Write};
+/// struct Position(f32, f32);
+///
+/// impl SnapshotComponent for Position {
+///     fn write_snapshot(&self, writer: &mut impl Write) -> io::Result<()> {
+///         self.0.write_snapshot(writer)?;
+///         self.1.write_snapshot(writer)
+///     }
+///
+///     fn read_snapshot(reader: &mut impl Read) -> io::Result<Self> {
+///         Ok(Position(f32::read_snapshot(reader)?, f32::read_snapshot(reader)?))
+///     }
+///
+///     fn type_hash() -> u64 {
+///         TypeHasher::default().bytes(b"Position").finish()
+///     }
+/// }
+/// ```
+pub trait SnapshotComponent: Sized {
+    fn write_snapshot(&self, writer: &mut impl Write) -> io::Result<()>;
+
+    fn read_snapshot(reader: &mut impl Read) -> io::Result<Self>;
+
+    /// Written in the snapshot header, snapshots with another hash are rejected.
+    /// Usually a `TypeHasher` hash of a fixed name for the type, to be changed
+    /// to reject old snapshots after changing the encoding
+    fn type_hash() -> u64;
+}
+
+/// A component storage that can be written in world snapshots,
+/// as the owners bitset followed by the packed components in order of entity index
+pub trait SnapshotStorage: Sized {
+    fn write_snapshot(&self, writer: &mut impl Write) -> io::Result<()>;
+
+    fn read_snapshot(reader: &mut impl Read) -> Result<Self, SnapshotError>;
+
+    /// Hash of the storage type and of the component type
+    fn type_hash() -> u64;
+}
+
+fn invalid_data(msg: impl std::fmt::Display) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+macro_rules! impl_snapshot_num {
+    ($($t: ty),*) => {
+        $(
+            impl SnapshotComponent for $t {
+                fn write_snapshot(&self, writer: &mut impl Write) -> io::Result<()> {
+                    writer.write_all(&self.to_le_bytes())
+                }
+
+                fn read_snapshot(reader: &mut impl Read) -> io::Result<Self> {
+                    let mut bytes = [0; size_of::<$t>()];
+                    reader.read_exact(&mut bytes)?;
+                    Ok(<$t>::from_le_bytes(bytes))
+                }
+
+                fn type_hash() -> u64 {
+                    TypeHasher::default().bytes(stringify!($t).as_bytes()).finish()
+                }
+            }
+        )*
+    };
+}
+
+impl_snapshot_num!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128, f32, f64);
+
+/// Written as a `u64` so snapshots are the same on every platform
+impl SnapshotComponent for usize {
+    fn write_snapshot(&self, writer: &mut impl Write) -> io::Result<()> {
+        (*self as u64).write_snapshot(writer)
+    }
+
+    fn read_snapshot(reader: &mut impl Read) -> io::Result<Self> {
+        usize::try_from(u64::read_snapshot(reader)?).map_err(invalid_data)
+    }
+
+    fn type_hash() -> u64 {
+        u64::type_hash()
+    }
+}
+
+/// Written as an `i64` so snapshots are the same on every platform
+impl SnapshotComponent for isize {
+    fn write_snapshot(&self, writer: &mut impl Write) -> io::Result<()> {
+        (*self as i64).write_snapshot(writer)
+    }
+
+    fn read_snapshot(reader: &mut impl Read) -> io::Result<Self> {
+        isize::try_from(i64::read_snapshot(reader)?).map_err(invalid_data)
+    }
+
+    fn type_hash() -> u64 {
+        i64::type_hash()
+    }
+}
+
+impl SnapshotComponent for bool {
+    fn write_snapshot(&self, writer: &mut impl Write) -> io::Result<()> {
+        (*self as u8).write_snapshot(writer)
+    }
+
+    fn read_snapshot(reader: &mut impl Read) -> io::Result<Self> {
+        match u8::read_snapshot(reader)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            byte => Err(invalid_data(format!("invalid bool {byte}"))),
+        }
+    }
+
+    fn type_hash() -> u64 {
+        TypeHasher::default().bytes(b"bool").finish()
+    }
+}
+
+impl SnapshotComponent for char {
+    fn write_snapshot(&self, writer: &mut impl Write) -> io::Result<()> {
+        (*self as u32).write_snapshot(writer)
+    }
+
+    fn read_snapshot(reader: &mut impl Read) -> io::Result<Self> {
+        let code = u32::read_snapshot(reader)?;
+        char::from_u32(code).ok_or_else(|| invalid_data(format!("invalid char {code}")))
+    }
+
+    fn type_hash() -> u64 {
+        TypeHasher::default().bytes(b"char").finish()
+    }
+}
+
+impl SnapshotComponent for () {
+    fn write_snapshot(&self, _writer: &mut impl Write) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn read_snapshot(_reader: &mut impl Read) -> io::Result<Self> {
+        Ok(())
+    }
+
+    fn type_hash() -> u64 {
+        TypeHasher::default().bytes(b"()").finish()
+    }
+}
+
+impl SnapshotComponent for String {
+    fn write_snapshot(&self, writer: &mut impl Write) -> io::Result<()> {
+        self.len().write_snapshot(writer)?;
+        writer.write_all(self.as_bytes())
+    }
+
+    fn read_snapshot(reader: &mut impl Read) -> io::Result<Self> {
+        let len = usize::read_snapshot(reader)?;
+        let mut bytes = Vec::new();
+        reader.take(len as u64).read_to_end(&mut bytes)?;
+        if bytes.len() != len {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        String::from_utf8(bytes).map_err(invalid_data)
+    }
+
+    fn type_hash() -> u64 {
+        TypeHasher::default().bytes(b"String").finish()
+    }
+}
+
+impl<T: SnapshotComponent> SnapshotComponent for Vec<T> {
+    fn write_snapshot(&self, writer: &mut impl Write) -> io::Result<()> {
+        self.len().write_snapshot(writer)?;
+        self.iter().try_for_each(|elem| elem.write_snapshot(writer))
+    }
+
+    fn read_snapshot(reader: &mut impl Read) -> io::Result<Self> {
+        let len = usize::read_snapshot(reader)?;
+        // the length isn't trusted for the allocation
+        let mut vec = Vec::with_capacity(len.min(1024));
+        for _ in 0..len {
+            vec.push(T::read_snapshot(reader)?);
+        }
+        Ok(vec)
+    }
+
+    fn type_hash() -> u64 {
+        TypeHasher::default()
+            .bytes(b"Vec")
+            .hash(T::type_hash())
+            .finish()
+    }
+}
+
+impl<T: SnapshotComponent> SnapshotComponent for Option<T> {
+    fn write_snapshot(&self, writer: &mut impl Write) -> io::Result<()> {
+        self.is_some().write_snapshot(writer)?;
+        match self {
+            Some(elem) => elem.write_snapshot(writer),
+            None => Ok(()),
+        }
+    }
+
+    fn read_snapshot(reader: &mut impl Read) -> io::Result<Self> {
+        if bool::read_snapshot(reader)? {
+            Ok(Some(T::read_snapshot(reader)?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn type_hash() -> u64 {
+        TypeHasher::default()
+            .bytes(b"Option")
+            .hash(T::type_hash())
+            .finish()
+    }
+}
+
+impl<T: SnapshotComponent, const N: usize> SnapshotComponent for [T; N] {
+    fn write_snapshot(&self, writer: &mut impl Write) -> io::Result<()> {
+        self.iter().try_for_each(|elem| elem.write_snapshot(writer))
+    }
+
+    fn read_snapshot(reader: &mut impl Read) -> io::Result<Self> {
+        let mut vec = Vec::with_capacity(N);
+        for _ in 0..N {
+            vec.push(T::read_snapshot(reader)?);
+        }
+        Ok(vec
+            .try_into()
+            .unwrap_or_else(|_| unreachable!("N elements were read")))
+    }
+
+    fn type_hash() -> u64 {
+        TypeHasher::default()
+            .bytes(b"Array")
+            .hash(N as u64)
+            .hash(T::type_hash())
+            .finish()
+    }
+}
+
+macro_rules! impl_snapshot_tuple {
+    ($($t: ident),*) => {
+        #[allow(non_snake_case)]
+        impl<$($t: SnapshotComponent),*> SnapshotComponent for ($($t,)*) {
+            fn write_snapshot(&self, writer: &mut impl Write) -> io::Result<()> {
+                let ($($t,)*) = self;
+                $($t.write_snapshot(writer)?;)*
+                Ok(())
+            }
+
+            fn read_snapshot(reader: &mut impl Read) -> io::Result<Self> {
+                Ok(($($t::read_snapshot(reader)?,)*))
+            }
+
+            fn type_hash() -> u64 {
+                TypeHasher::default().bytes(b"Tuple")$(.hash($t::type_hash()))*.finish()
+            }
+        }
+    };
+}
+
+impl_snapshot_tuple!(A);
+impl_snapshot_tuple!(A, B);
+impl_snapshot_tuple!(A, B, C);
+impl_snapshot_tuple!(A, B, C, D);
+
+/// Components can refer to other entities
+impl SnapshotComponent for EntityHandle {
+    fn write_snapshot(&self, writer: &mut impl Write) -> io::Result<()> {
+        self.index().write_snapshot(writer)?;
+        self.generation().write_snapshot(writer)
+    }
+
+    fn read_snapshot(reader: &mut impl Read) -> io::Result<Self> {
+        Ok(EntityHandle::new(
+            usize::read_snapshot(reader)?,
+            u32::read_snapshot(reader)?,
+        ))
+    }
+
+    fn type_hash() -> u64 {
+        TypeHasher::default().bytes(b"EntityHandle").finish()
+    }
+}
+
+/// The bits of a bitset as 64 bit words, the same on every platform.
+/// Shared by the snapshots and the serde impls
+pub(crate) fn bitset_words(bits: &FixedBitSet) -> Vec<u64> {
+    let mut words = vec![0u64; bits.len().div_ceil(64)];
+    for index in bits.ones() {
+        words[index / 64] |= 1 << (index % 64);
+    }
+    words
+}
+
+/// Bitset of length `len` from the `len.div_ceil(64)` words of `bitset_words`,
+/// erroring with `invalid` if bits past the length are set
+pub(crate) fn bitset_from_words<E>(
+    len: usize,
+    words: impl Iterator<Item = Result<u64, E>>,
+    invalid: impl FnOnce(String) -> E,
+) -> Result<FixedBitSet, E> {
+    let mut bits = FixedBitSet::new();
+    for (word_ind, word) in words.take(len.div_ceil(64)).enumerate() {
+        let mut word = word?;
+        while word != 0 {
+            let index = word_ind * 64 + word.trailing_zeros() as usize;
+            if index >= len {
+                return Err(invalid(format!(
+                    "bit {index} set past the bitset length {len}"
+                )));
+            }
+            bits.grow_and_insert(index);
+            word &= word - 1;
+        }
+    }
+    bits.grow(len);
+    Ok(bits)
+}
+
+pub(crate) fn write_bitset(writer: &mut impl Write, bits: &FixedBitSet) -> io::Result<()> {
+    bits.len().write_snapshot(writer)?;
+    bitset_words(bits)
+        .iter()
+        .try_for_each(|word| word.write_snapshot(writer))
+}
+
+pub(crate) fn read_bitset(reader: &mut impl Read) -> Result<FixedBitSet, SnapshotError> {
+    let len = usize::read_snapshot(reader)?;
+    let words = std::iter::repeat_with(|| Ok(u64::read_snapshot(reader)?));
+    bitset_from_words(len, words, SnapshotError::Invalid)
+}
+
+/// Writes the owners, then the generation and the encoding of every component.
+/// `comps` have to be sorted by entity index
+fn write_comps<'a, T: SnapshotComponent + 'a>(
+    writer: &mut impl Write,
+    owners: &FixedBitSet,
+    comps: impl Iterator<Item = (EntityHandle, &'a T)>,
+) -> io::Result<()> {
+    write_bitset(writer, owners)?;
+    for (id, comp) in comps {
+        id.generation().write_snapshot(writer)?;
+        comp.write_snapshot(writer)?;
+    }
+    Ok(())
+}
+
+fn read_comps<T: SnapshotComponent>(
+    reader: &mut impl Read,
+) -> Result<Vec<(EntityHandle, T)>, SnapshotError> {
+    let owners = read_bitset(reader)?;
+    let mut comps = Vec::with_capacity(owners.count_ones(..));
+    for index in owners.ones() {
+        let id = EntityHandle::new(index, u32::read_snapshot(reader)?);
+        comps.push((id, T::read_snapshot(reader)?));
+    }
+    Ok(comps)
+}
+
+fn storage_hash<T: SnapshotComponent>(storage: &str) -> u64 {
+    TypeHasher::default()
+        .bytes(storage.as_bytes())
+        .hash(T::type_hash())
+        .finish()
+}
+
+impl<T: SnapshotComponent> SnapshotStorage for CompVec<T> {
+    fn write_snapshot(&self, writer: &mut impl Write) -> io::Result<()> {
+        write_comps(writer, self.owners(), self.iter())
+    }
+
+    fn read_snapshot(reader: &mut impl Read) -> Result<Self, SnapshotError> {
+        let mut vec = CompVec::default();
+        vec.insert_many(read_comps(reader)?);
+        Ok(vec)
+    }
+
+    fn type_hash() -> u64 {
+        storage_hash::<T>("CompVec")
+    }
+}
+
+impl<T: SnapshotComponent> SnapshotStorage for SparseCompVec<T> {
+    fn write_snapshot(&self, writer: &mut impl Write) -> io::Result<()> {
+        let mut comps: Vec<_> = self.iter().collect();
+        comps.sort_unstable_by_key(|(id, _)| id.index());
+        write_comps(writer, self.owners(), comps.into_iter())
+    }
+
+    fn read_snapshot(reader: &mut impl Read) -> Result<Self, SnapshotError> {
+        let mut vec = SparseCompVec::default();
+        for (id, comp) in read_comps(reader)? {
+            vec.insert(id, comp);
+        }
+        Ok(vec)
+    }
+
+    fn type_hash() -> u64 {
+        storage_hash::<T>("SparseCompVec")
+    }
+}
+
+impl<T: SnapshotComponent> SnapshotStorage for DenseCompVec<T> {
+    fn write_snapshot(&self, writer: &mut impl Write) -> io::Result<()> {
+        write_comps(writer, self.owners(), self.iter())
+    }
+
+    fn read_snapshot(reader: &mut impl Read) -> Result<Self, SnapshotError> {
+        let mut vec = DenseCompVec::default();
+        for (id, comp) in read_comps(reader)? {
+            vec.insert(id, comp);
+        }
+        Ok(vec)
+    }
+
+    fn type_hash() -> u64 {
+        storage_hash::<T>("DenseCompVec")
+    }
+}
+
+impl SnapshotStorage for TagVec {
+    fn write_snapshot(&self, writer: &mut impl Write) -> io::Result<()> {
+        write_comps(writer, self.owners(), self.iter().map(|id| (id, &())))
+    }
+
+    fn read_snapshot(reader: &mut impl Read) -> Result<Self, SnapshotError> {
+        let mut vec = TagVec::default();
+        for (id, ()) in read_comps(reader)? {
+            vec.insert(id);
+        }
+        Ok(vec)
+    }
+
+    fn type_hash() -> u64 {
+        storage_hash::<()>("TagVec")
+    }
+}
+
+impl<T: SnapshotComponent + Hash + Eq + Clone> SnapshotStorage for IndexedCompVec<T> {
+    fn write_snapshot(&self, writer: &mut impl Write) -> io::Result<()> {
+        write_comps(writer, self.owners(), self.iter())
+    }
+
+    fn read_snapshot(reader: &mut impl Read) -> Result<Self, SnapshotError> {
+        let mut vec = IndexedCompVec::default();
+        for (id, comp) in read_comps(reader)? {
+            vec.insert(id, comp);
+        }
+        Ok(vec)
+    }
+
+    fn type_hash() -> u64 {
+        storage_hash::<T>("IndexedCompVec")
+    }
+}
+
+impl<T: SnapshotComponent + Hash + Eq + Clone> SnapshotStorage for UniqueCompVec<T> {
+    fn write_snapshot(&self, writer: &mut impl Write) -> io::Result<()> {
+        write_comps(writer, self.owners(), self.iter())
+    }
+
+    /// Errors if two entities have the same value
+    fn read_snapshot(reader: &mut impl Read) -> Result<Self, SnapshotError> {
+        let mut vec = UniqueCompVec::default();
+        for (id, comp) in read_comps(reader)? {
+            vec.insert(id, comp).map_err(|conflict| {
+                SnapshotError::Invalid(format!(
+                    "entities {:?} and {id:?} have the same unique component",
+                    conflict.existing
+                ))
+            })?;
+        }
+        Ok(vec)
+    }
+
+    fn type_hash() -> u64 {
+        storage_hash::<T>("UniqueCompVec")
+    }
+}
+
+/// Writes the snapshot header: the format version and the name and type hash of every field.
+/// Used by `#[world(snapshot)]`
+pub fn write_snapshot_header(writer: &mut impl Write, fields: &[(&str, u64)]) -> io::Result<()> {
+    writer.write_all(&MAGIC)?;
+    SNAPSHOT_VERSION.write_snapshot(writer)?;
+    fields.len().write_snapshot(writer)?;
+    for (name, hash) in fields {
+        name.to_string().write_snapshot(writer)?;
+        hash.write_snapshot(writer)?;
+    }
+    Ok(())
+}
+
+/// Reads the snapshot header, erroring if the fields don't match `fields`.
+/// Used by `#[world(snapshot)]`
+pub fn read_snapshot_header(
+    reader: &mut impl Read,
+    fields: &[(&str, u64)],
+) -> Result<(), SnapshotError> {
+    let mut magic = [0; 4];
+    reader.read_exact(&mut magic)?;
+    if magic != MAGIC {
+        return Err(SnapshotError::BadMagic);
+    }
+    let version = u32::read_snapshot(reader)?;
+    if version != SNAPSHOT_VERSION {
+        return Err(SnapshotError::UnsupportedVersion(version));
+    }
+    let found = Vec::<(String, u64)>::read_snapshot(reader)?;
+
+    let names = |fields: &mut dyn Iterator<Item = &str>| fields.collect::<Vec<_>>().join(", ");
+    if found.len() != fields.len()
+        || found
+            .iter()
+            .zip(fields)
+            .any(|((found_name, _), (name, _))| found_name != name)
+    {
+        return Err(SnapshotError::SchemaMismatch(format!(
+            "the snapshot has the fields [{}] but the world has [{}]",
+            names(&mut found.iter().map(|(name, _)| name.as_str())),
+            names(&mut fields.iter().map(|(name, _)| *name)),
+        )));
+    }
+    for ((name, found_hash), (_, hash)) in found.iter().zip(fields) {
+        if found_hash != hash {
+            return Err(SnapshotError::SchemaMismatch(format!(
+                "field `{name}` has a different type in the snapshot"
+            )));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{CompVec, EntityHandleCounter, SnapshotComponent, SnapshotStorage, TagVec};
+
+    #[test]
+    fn test_storage_roundtrip() {
+        let mut handles = EntityHandleCounter::default();
+        let mut names = CompVec::<(String, Option<u8>)>::default();
+        let mut tags = TagVec::default();
+        for i in 0..700 {
+            let id = handles.next_handle();
+            if i % 3 == 0 {
+                names.insert(id, (format!("e{i}"), (i % 2 == 0).then_some(i as u8)));
+            }
+            if i % 5 == 0 {
+                tags.insert(id);
+            }
+        }
+
+        let mut bytes = Vec::new();
+        names.write_snapshot(&mut bytes).unwrap();
+        tags.write_snapshot(&mut bytes).unwrap();
+        let mut reader = bytes.as_slice();
+        let names2 = CompVec::read_snapshot(&mut reader).unwrap();
+        let tags2 = TagVec::read_snapshot(&mut reader).unwrap();
+        assert!(reader.is_empty());
+        assert_eq!(names, names2);
+        assert!(tags.iter().eq(tags2.iter()));
+
+        // truncated
+        assert!(CompVec::<(String, Option<u8>)>::read_snapshot(&mut &bytes[..100]).is_err());
+        assert_ne!(<CompVec<u32>>::type_hash(), <CompVec<u64>>::type_hash());
+        assert_ne!(u32::type_hash(), i32::type_hash());
+    }
+}
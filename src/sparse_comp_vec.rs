@@ -0,0 +1,376 @@
+use crate::comp_iter::{CompIterer, NonOptionalCompIterer, Optional};
+use crate::{CompStorage, EntityHandle};
+use fixedbitset::FixedBitSet;
+
+#[derive(Debug, Clone)]
+/// A sparse set of components: an array indexed by entity index giving the
+/// position of the component in a packed `Vec<(EntityHandle, T)>`.
+///
+/// Get, insert and remove are O(1). Removing moves the last component into
+/// the hole, so the packed components are not sorted by entity index until
+/// `iter_mut` puts the moved ones back in order. Useful for components that are
+/// added and removed a lot.
+pub struct SparseCompVec<T> {
+    /// Position in `dense` of the component of every entity index,
+    /// only meaningful for indices in `owners`
+    sparse: Vec<usize>,
+    dense: Vec<(EntityHandle, T)>,
+    owners: FixedBitSet,
+    /// Entity indices whose component was moved or appended out of entity index
+    /// order since the last `iter_mut`, the others are sorted
+    moved: FixedBitSet,
+}
+
+impl<T> Default for SparseCompVec<T> {
+    fn default() -> Self {
+        Self {
+            sparse: Default::default(),
+            dense: Default::default(),
+            owners: Default::default(),
+            moved: Default::default(),
+        }
+    }
+}
+
+impl<T> SparseCompVec<T> {
+    pub fn get(&self, id: EntityHandle) -> Option<&T> {
+        if self.owners.contains(id.index()) {
+            let (id_out, comp) = &self.dense[self.sparse[id.index()]];
+            if *id_out == id {
+                return Some(comp);
+            }
+        }
+        None
+    }
+
+    pub fn get_mut(&mut self, id: EntityHandle) -> Option<&mut T> {
+        if self.owners.contains(id.index()) {
+            let (id_out, comp) = &mut self.dense[self.sparse[id.index()]];
+            if *id_out == id {
+                return Some(comp);
+            }
+        }
+        None
+    }
+
+    /// Returns the previous element if it was there
+    pub fn insert(&mut self, id: EntityHandle, comp: T) -> Option<T> {
+        if self.owners.contains(id.index()) {
+            let dense_ind = self.sparse[id.index()];
+            let (old_id, comp) = std::mem::replace(&mut self.dense[dense_ind], (id, comp));
+            assert_eq!(old_id, id);
+            Some(comp)
+        } else {
+            self.owners.grow_and_insert(id.index());
+            if id.index() >= self.sparse.len() {
+                self.sparse.resize(id.index() + 1, 0);
+            }
+            // the last component is the largest sorted one unless it was moved
+            if self.dense.last().is_some_and(|(last, _)| {
+                last.index() > id.index() || self.moved.contains(last.index())
+            }) {
+                self.moved.grow_and_insert(id.index());
+            }
+            self.sparse[id.index()] = self.dense.len();
+            self.dense.push((id, comp));
+            None
+        }
+    }
+
+    /// Returns the element if it was there
+    pub fn remove(&mut self, id: EntityHandle) -> Option<T> {
+        if !self.owners.contains(id.index()) {
+            return None;
+        }
+        let dense_ind = self.sparse[id.index()];
+        if self.dense[dense_ind].0 != id {
+            return None;
+        }
+        self.owners.remove(id.index());
+        if self.moved.contains(id.index()) {
+            self.moved.remove(id.index());
+        }
+        let (_, comp) = self.dense.swap_remove(dense_ind);
+        if let Some((moved, _)) = self.dense.get(dense_ind) {
+            self.sparse[moved.index()] = dense_ind;
+            self.moved.grow_and_insert(moved.index());
+        }
+        Some(comp)
+    }
+
+    /// Returns a slice of the packed components, in no particular order
+    pub fn components(&self) -> &[(EntityHandle, T)] {
+        &self.dense
+    }
+
+    /// Iterator of the packed components. Can be used with `CompIter` to iterate
+    /// over components with shared ownership
+    pub fn iter(&self) -> SparseIter<'_, T> {
+        SparseIter {
+            next_dense_ind: 0,
+            sparse: &self.sparse,
+            dense: &self.dense,
+            owners: &self.owners,
+        }
+    }
+
+    /// Mutable Iterator of the packed components. Can be used with `CompIter` to iterate
+    /// over components with shared ownership
+    ///
+    /// Puts the components moved by removals and inserts back in entity index
+    /// order first, so they can be handed out in order. This sorts only the moved
+    /// components and merges them with the others, O(n + k log k) for k moved ones
+    pub fn iter_mut(&mut self) -> SparseIterMut<'_, T> {
+        self.sort();
+        SparseIterMut {
+            next_dense_ind: 0,
+            sparse: &self.sparse,
+            dense: &mut self.dense,
+            owners: &self.owners,
+        }
+    }
+
+    fn sort(&mut self) {
+        if self.moved.is_clear() {
+            return;
+        }
+        let moved = &self.moved;
+        let mut displaced: Vec<_> = self
+            .dense
+            .extract_if(.., |(id, _)| moved.contains(id.index()))
+            .collect();
+        displaced.sort_unstable_by_key(|(id, _)| id.index());
+        // two sorted runs, which the stable sort merges in linear time
+        self.dense.append(&mut displaced);
+        self.dense.sort_by_key(|(id, _)| id.index());
+        for (dense_ind, (id, _)) in self.dense.iter().enumerate() {
+            self.sparse[id.index()] = dense_ind;
+        }
+        self.moved.clear();
+    }
+
+    pub fn owners(&self) -> &FixedBitSet {
+        &self.owners
+    }
+
+    /// Number of components
+    pub fn len(&self) -> usize {
+        self.dense.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.dense.is_empty()
+    }
+}
+
+impl<T> CompStorage for SparseCompVec<T> {
+    fn owners(&self) -> &FixedBitSet {
+        &self.owners
+    }
+
+    fn remove_entity(&mut self, handle: EntityHandle) {
+        self.remove(handle);
+    }
+
+    fn is_empty(&self) -> bool {
+        self.dense.is_empty()
+    }
+}
+
+/// Iterator for SparseCompVec<T>.
+///
+/// Iterates in packed order on its own, but looks components up by
+/// entity index when used in `CompIter`
+pub struct SparseIter<'a, T> {
+    next_dense_ind: usize,
+    sparse: &'a [usize],
+    dense: &'a [(EntityHandle, T)],
+    owners: &'a FixedBitSet,
+}
+
+impl<T> SparseIter<'_, T> {
+    /// Make the iter optional, meaning it will not affect ownership in `CompIter`
+    /// and will return Option<T> for every set of components.
+    pub fn optional(self) -> Optional<Self> {
+        Optional(self)
+    }
+}
+
+impl<'a, T> Iterator for SparseIter<'a, T> {
+    type Item = (EntityHandle, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let dense = self.dense;
+        let (id, comp) = dense.get(self.next_dense_ind)?;
+        self.next_dense_ind += 1;
+        Some((*id, comp))
+    }
+}
+
+impl<'a, T> CompIterer for SparseIter<'a, T> {
+    type Item = &'a T;
+
+    fn combine_owners(&self, owners: &mut FixedBitSet) {
+        owners.intersect_with(self.owners);
+    }
+
+    fn comp_at(&mut self, entity_handle: EntityHandle) -> Self::Item {
+        let (handle2, comp) = self.comp_at_index(entity_handle.index());
+        assert_eq!(entity_handle, handle2);
+        comp
+    }
+}
+
+impl<T> NonOptionalCompIterer for SparseIter<'_, T> {
+    fn owners(&self) -> &FixedBitSet {
+        self.owners
+    }
+
+    fn comp_at_index(&mut self, entity_index: usize) -> (EntityHandle, Self::Item) {
+        let dense = self.dense;
+        let (id, comp) = &dense[self.sparse[entity_index]];
+        (*id, comp)
+    }
+}
+
+/// Mut iterator for SparseCompVec<T>.
+///
+/// Iterates over the packed components sorted by entity index
+pub struct SparseIterMut<'a, T> {
+    /// Position in the packed components of the start of `dense`
+    next_dense_ind: usize,
+    sparse: &'a [usize],
+    /// Components not handed out yet
+    dense: &'a mut [(EntityHandle, T)],
+    owners: &'a FixedBitSet,
+}
+
+impl<T> SparseIterMut<'_, T> {
+    /// Make the iter optional, meaning it will not affect ownership in `CompIter`
+    /// and will return Option<T> for every set of components.
+    pub fn optional(self) -> Optional<Self> {
+        Optional(self)
+    }
+}
+
+impl<'a, T> Iterator for SparseIterMut<'a, T> {
+    type Item = (EntityHandle, &'a mut T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let ((id, comp), rest) = std::mem::take(&mut self.dense).split_first_mut()?;
+        self.dense = rest;
+        self.next_dense_ind += 1;
+        Some((*id, comp))
+    }
+}
+
+impl<'a, T> CompIterer for SparseIterMut<'a, T> {
+    type Item = &'a mut T;
+
+    fn combine_owners(&self, owners: &mut FixedBitSet) {
+        owners.intersect_with(self.owners);
+    }
+
+    fn comp_at(&mut self, entity_handle: EntityHandle) -> Self::Item {
+        let (handle2, comp) = self.comp_at_index(entity_handle.index());
+        assert_eq!(entity_handle, handle2);
+        comp
+    }
+}
+
+impl<T> NonOptionalCompIterer for SparseIterMut<'_, T> {
+    fn owners(&self) -> &FixedBitSet {
+        self.owners
+    }
+
+    fn comp_at_index(&mut self, entity_index: usize) -> (EntityHandle, Self::Item) {
+        let skip = self.sparse[entity_index]
+            .checked_sub(self.next_dense_ind)
+            .expect("component was already handed out");
+        let dense = std::mem::take(&mut self.dense);
+        let ((id, comp), rest) = dense[skip..].split_first_mut().unwrap();
+        self.dense = rest;
+        self.next_dense_ind += skip + 1;
+        (*id, comp)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{CompIter, CompVec, EntityHandleCounter, SparseCompVec};
+
+    #[test]
+    fn test_swap_remove() {
+        let mut v = SparseCompVec::<usize>::default();
+        let mut handles = EntityHandleCounter::default();
+        let ids: Vec<_> = (0..10).map(|_| handles.next_handle()).collect();
+        for id in ids.iter() {
+            v.insert(*id, id.index());
+        }
+
+        assert_eq!(v.remove(ids[2]), Some(2));
+        assert_eq!(v.remove(ids[2]), None);
+        // a stale handle of a respawned index doesn't remove the new component
+        handles.entity_deleted(ids[2]);
+        let respawned = handles.next_handle();
+        v.insert(respawned, 20);
+        assert_eq!(v.remove(ids[2]), None);
+        assert_eq!(v.remove(respawned), Some(20));
+        assert_eq!(v.remove(ids[0]), Some(0));
+        assert_eq!(v.len(), 8);
+        for id in ids.iter() {
+            let expected = (id.index() != 0 && id.index() != 2).then_some(id.index());
+            assert_eq!(v.get(*id).copied(), expected);
+        }
+
+        // the removals moved the last components, iter_mut sorts them back
+        let order: Vec<_> = v.iter_mut().map(|(id, _)| id.index()).collect();
+        assert_eq!(order, vec![1, 3, 4, 5, 6, 7, 8, 9]);
+        assert_eq!(v.get(ids[9]), Some(&9));
+
+        let mut other = CompVec::<bool>::default();
+        for id in ids.iter().step_by(3) {
+            other.insert(*id, true);
+        }
+        for (id, comp, _) in CompIter::from((v.iter_mut(), other.iter())) {
+            *comp += 100;
+            assert_eq!(id.index() % 3, 0);
+        }
+        let joined: Vec<_> = CompIter::from((other.iter(), v.iter().optional()))
+            .into_iter()
+            .map(|(id, _, comp)| (id.index(), comp.copied()))
+            .collect();
+        assert_eq!(
+            joined,
+            vec![(0, None), (3, Some(103)), (6, Some(106)), (9, Some(109))]
+        );
+    }
+
+    #[test]
+    fn test_churn_order() {
+        let mut v = SparseCompVec::<usize>::default();
+        let mut handles = EntityHandleCounter::default();
+        let mut live = vec![];
+        // deterministic mix of spawns and despawns at scattered positions
+        for step in 0..500usize {
+            if step % 3 == 2 && !live.is_empty() {
+                let id = live.swap_remove(step * 7 % live.len());
+                assert_eq!(v.remove(id), Some(id.index()));
+                handles.entity_deleted(id);
+            } else {
+                let id = handles.next_handle();
+                v.insert(id, id.index());
+                live.push(id);
+            }
+            if step % 50 == 0 {
+                let order: Vec<_> = v.iter_mut().map(|(id, comp)| (id.index(), *comp)).collect();
+                let mut expected: Vec<_> = live.iter().map(|id| (id.index(), id.index())).collect();
+                expected.sort_unstable();
+                assert_eq!(order, expected);
+            }
+        }
+        for id in live.iter() {
+            assert_eq!(v.get(*id), Some(&id.index()));
+        }
+    }
+}
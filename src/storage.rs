@@ -0,0 +1,16 @@
+use crate::EntityHandle;
+use fixedbitset::FixedBitSet;
+
+/// Interface shared by all the component storages (`CompVec`, `SparseCompVec`, ...).
+///
+/// Used by the `CompIter` filters and by `#[derive(World)]` to delete the
+/// components of an entity, so any type implementing it can be a world field.
+pub trait CompStorage {
+    /// Bitset of the entity indices that have a component in the storage
+    fn owners(&self) -> &FixedBitSet;
+
+    /// Removes and drops the component of the entity, if it has one
+    fn remove_entity(&mut self, handle: EntityHandle);
+
+    fn is_empty(&self) -> bool;
+}
@@ -0,0 +1,207 @@
+use crate::comp_iter::{CompIterer, NonOptionalCompIterer, Optional};
+use crate::{CompStorage, EntityHandle};
+use fixedbitset::FixedBitSet;
+
+#[derive(Debug, Default, Clone)]
+/// Storage for marker components without data, like a `CompVec<()>`
+/// that only keeps the owners bitset and the generation of every owner.
+///
+/// Mostly useful as a filter with `CompIter::with`/`without`, or as
+/// an optional iterator telling if an entity has the tag.
+pub struct TagVec {
+    owners: FixedBitSet,
+    /// Generation of the entity owning every index,
+    /// only meaningful for indices in `owners`
+    generations: Vec<u32>,
+}
+
+impl TagVec {
+    pub fn contains(&self, id: EntityHandle) -> bool {
+        self.owners.contains(id.index()) && self.generations[id.index()] == id.generation()
+    }
+
+    /// Returns whether the entity already had the tag
+    pub fn insert(&mut self, id: EntityHandle) -> bool {
+        let already_had = self.owners.contains(id.index());
+        if already_had {
+            assert_eq!(self.generations[id.index()], id.generation());
+        } else {
+            self.owners.grow_and_insert(id.index());
+            if id.index() >= self.generations.len() {
+                self.generations.resize(id.index() + 1, 0);
+            }
+            self.generations[id.index()] = id.generation();
+        }
+        already_had
+    }
+
+    /// Returns whether the entity had the tag, a stale handle never has it
+    pub fn remove(&mut self, id: EntityHandle) -> bool {
+        let had = self.contains(id);
+        if had {
+            self.owners.remove(id.index());
+        }
+        had
+    }
+
+    /// Iterator of the tagged entities. Can be used with `CompIter` to iterate
+    /// over components with shared ownership
+    pub fn iter(&self) -> TagIter<'_> {
+        TagIter {
+            ones: self.owners.ones(),
+            generations: &self.generations,
+            owners: &self.owners,
+        }
+    }
+
+    pub fn owners(&self) -> &FixedBitSet {
+        &self.owners
+    }
+
+    /// Number of tagged entities
+    pub fn len(&self) -> usize {
+        self.owners.count_ones(..)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.owners.is_clear()
+    }
+}
+
+impl CompStorage for TagVec {
+    fn owners(&self) -> &FixedBitSet {
+        &self.owners
+    }
+
+    fn remove_entity(&mut self, handle: EntityHandle) {
+        self.remove(handle);
+    }
+
+    fn is_empty(&self) -> bool {
+        self.owners.is_clear()
+    }
+}
+
+/// Iterator for TagVec, yields `()` as the component when used in `CompIter`
+pub struct TagIter<'a> {
+    ones: fixedbitset::Ones<'a>,
+    generations: &'a [u32],
+    owners: &'a FixedBitSet,
+}
+
+impl TagIter<'_> {
+    /// Make the iter optional, meaning it will not affect ownership in `CompIter`
+    /// and will return Option<()> for every set of components.
+    pub fn optional(self) -> Optional<Self> {
+        Optional(self)
+    }
+}
+
+impl Iterator for TagIter<'_> {
+    type Item = EntityHandle;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.ones
+            .next()
+            .map(|index| EntityHandle::new(index, self.generations[index]))
+    }
+}
+
+impl CompIterer for TagIter<'_> {
+    type Item = ();
+
+    fn combine_owners(&self, owners: &mut FixedBitSet) {
+        owners.intersect_with(self.owners);
+    }
+
+    fn comp_at(&mut self, entity_handle: EntityHandle) -> Self::Item {
+        assert_eq!(
+            self.generations[entity_handle.index()],
+            entity_handle.generation()
+        );
+    }
+}
+
+impl NonOptionalCompIterer for TagIter<'_> {
+    fn owners(&self) -> &FixedBitSet {
+        self.owners
+    }
+
+    fn comp_at_index(&mut self, entity_index: usize) -> (EntityHandle, Self::Item) {
+        (
+            EntityHandle::new(entity_index, self.generations[entity_index]),
+            (),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{CompIter, CompVec, EntityHandleCounter, TagVec};
+
+    #[test]
+    fn test_tags() {
+        let mut handles = EntityHandleCounter::default();
+        let ids: Vec<_> = (0..6).map(|_| handles.next_handle()).collect();
+        let mut v = CompVec::<usize>::default();
+        let mut tags = TagVec::default();
+        for id in ids.iter() {
+            v.insert(*id, id.index());
+            if id.index() % 2 == 0 {
+                assert!(!tags.insert(*id));
+            }
+        }
+        assert!(tags.insert(ids[0]));
+        assert!(tags.remove(ids[4]));
+        assert!(!tags.contains(ids[4]));
+        assert_eq!(tags.iter().collect::<Vec<_>>(), vec![ids[0], ids[2]]);
+
+        let with: Vec<_> = CompIter::from((v.iter(),))
+            .with(&tags)
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect();
+        assert_eq!(with, vec![ids[0], ids[2]]);
+
+        let without: Vec<_> = CompIter::from((v.iter(),))
+            .without(&tags)
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect();
+        assert_eq!(without, vec![ids[1], ids[3], ids[4], ids[5]]);
+
+        let tagged: Vec<_> = CompIter::from((v.iter(), tags.iter().optional()))
+            .into_iter()
+            .map(|(_, comp, tag)| (*comp, tag.is_some()))
+            .collect();
+        assert_eq!(
+            tagged,
+            vec![
+                (0, true),
+                (1, false),
+                (2, true),
+                (3, false),
+                (4, false),
+                (5, false)
+            ]
+        );
+
+        let driven: Vec<_> = CompIter::from((tags.iter(), v.iter()))
+            .into_iter()
+            .map(|(id, (), comp)| (id, *comp))
+            .collect();
+        assert_eq!(driven, vec![(ids[0], 0), (ids[2], 2)]);
+    }
+
+    #[test]
+    fn test_remove_stale() {
+        let mut handles = EntityHandleCounter::default();
+        let old = handles.next_handle();
+        handles.entity_deleted(old);
+        let new = handles.next_handle();
+        let mut tags = TagVec::default();
+        tags.insert(new);
+        assert!(!tags.remove(old));
+        assert!(tags.contains(new));
+    }
+}
@@ -0,0 +1,225 @@
+use crate::comp_iter::Iter;
+use crate::{CompStorage, CompVec, EntityHandle};
+use fixedbitset::FixedBitSet;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+#[derive(Debug, Clone)]
+/// A `CompVec` where at most one entity can have a given component value,
+/// for `PlayerId`, `Name`, ... Keeps a map from every value to its entity.
+///
+/// Components can't be mutated in place, `insert` replaces them
+/// after checking the new value is free.
+pub struct UniqueCompVec<T> {
+    comps: CompVec<T>,
+    table: HashMap<T, EntityHandle>,
+}
+
+impl<T> Default for UniqueCompVec<T> {
+    fn default() -> Self {
+        Self {
+            comps: Default::default(),
+            table: Default::default(),
+        }
+    }
+}
+
+/// Error of `UniqueCompVec::insert` when another entity already has the value.
+/// Gives back the component that wasn't inserted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UniqueConflict<T> {
+    pub existing: EntityHandle,
+    pub comp: T,
+}
+
+impl<T> std::fmt::Display for UniqueConflict<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "component value already belongs to entity {:?}",
+            self.existing
+        )
+    }
+}
+
+impl<T: std::fmt::Debug> std::error::Error for UniqueConflict<T> {}
+
+impl<T: Hash + Eq + Clone> UniqueCompVec<T> {
+    pub fn get(&self, id: EntityHandle) -> Option<&T> {
+        self.comps.get(id)
+    }
+
+    /// Returns the previous element if it was there,
+    /// errors if another entity already has a component equal to `comp`
+    pub fn insert(&mut self, id: EntityHandle, comp: T) -> Result<Option<T>, UniqueConflict<T>> {
+        match self.table.get(&comp) {
+            Some(&existing) if existing != id => Err(UniqueConflict { existing, comp }),
+            // same value as the current component
+            Some(_) => Ok(self.comps.insert(id, comp)),
+            None => {
+                self.table.insert(comp.clone(), id);
+                let old = self.comps.insert(id, comp);
+                if let Some(old) = &old {
+                    self.table.remove(old);
+                }
+                Ok(old)
+            }
+        }
+    }
+
+    /// Returns the element if it was there
+    pub fn remove(&mut self, id: EntityHandle) -> Option<T> {
+        let comp = self.comps.remove(id)?;
+        self.table.remove(&comp);
+        Some(comp)
+    }
+
+    /// Only keeps the components for which `keep` returns true
+    pub fn retain(&mut self, mut keep: impl FnMut(EntityHandle, &T) -> bool) {
+        self.comps.retain(|id, comp| {
+            let kept = keep(id, comp);
+            if !kept {
+                self.table.remove(comp);
+            }
+            kept
+        });
+    }
+
+    /// The entity whose component equals `value`
+    pub fn handle_with(&self, value: &T) -> Option<EntityHandle> {
+        self.table.get(value).copied()
+    }
+
+    /// Whether an entity has a component equal to `value`
+    pub fn contains_value(&self, value: &T) -> bool {
+        self.table.contains_key(value)
+    }
+
+    /// Iterator of the components in order of entity index. Can be used with `CompIter`
+    /// to iterate over components with shared ownership
+    pub fn iter(&self) -> Iter<'_, T> {
+        self.comps.iter()
+    }
+
+    /// See `CompVec::is_dirty`
+    pub fn is_dirty(&self) -> bool {
+        self.comps.is_dirty()
+    }
+
+    pub fn clear_dirty(&mut self) {
+        self.comps.clear_dirty();
+    }
+
+    /// The underlying `CompVec`, for read-only access
+    pub fn comps(&self) -> &CompVec<T> {
+        &self.comps
+    }
+
+    pub fn owners(&self) -> &FixedBitSet {
+        self.comps.owners()
+    }
+
+    /// Number of components
+    pub fn len(&self) -> usize {
+        self.comps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.comps.is_empty()
+    }
+}
+
+/// Only compares the components, the map follows from them
+impl<T: PartialEq> PartialEq for UniqueCompVec<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.comps == other.comps
+    }
+}
+
+impl<T: Eq> Eq for UniqueCompVec<T> {}
+
+impl<T: Hash> Hash for UniqueCompVec<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.comps.hash(state);
+    }
+}
+
+/// Serialized as the underlying `CompVec`
+#[cfg(feature = "serde")]
+impl<T: serde::Serialize> serde::Serialize for UniqueCompVec<T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.comps.serialize(serializer)
+    }
+}
+
+/// Rebuilds the table from the deserialized `CompVec`,
+/// errors if two entities have the same value
+#[cfg(feature = "serde")]
+impl<'de, T: serde::Deserialize<'de> + Hash + Eq + Clone> serde::Deserialize<'de>
+    for UniqueCompVec<T>
+{
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let comps = CompVec::<T>::deserialize(deserializer)?;
+        let mut table = HashMap::with_capacity(comps.len());
+        for (id, comp) in comps.iter() {
+            if let Some(existing) = table.insert(comp.clone(), id) {
+                return Err(serde::de::Error::custom(format!(
+                    "entities {existing:?} and {id:?} have the same unique component"
+                )));
+            }
+        }
+        Ok(Self { comps, table })
+    }
+}
+
+impl<T: Hash + Eq + Clone> CompStorage for UniqueCompVec<T> {
+    fn owners(&self) -> &FixedBitSet {
+        self.comps.owners()
+    }
+
+    fn remove_entity(&mut self, handle: EntityHandle) {
+        self.remove(handle);
+    }
+
+    fn is_empty(&self) -> bool {
+        self.comps.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{EntityHandleCounter, UniqueCompVec, UniqueConflict};
+
+    #[test]
+    fn test_unique() {
+        let mut ids = UniqueCompVec::<u32>::default();
+        let mut handles = EntityHandleCounter::default();
+        let a = handles.next_handle();
+        let b = handles.next_handle();
+
+        assert_eq!(ids.insert(a, 1), Ok(None));
+        assert_eq!(
+            ids.insert(b, 1),
+            Err(UniqueConflict {
+                existing: a,
+                comp: 1
+            })
+        );
+        assert_eq!(ids.get(b), None);
+        assert_eq!(ids.insert(a, 1), Ok(Some(1)));
+        assert_eq!(ids.insert(b, 2), Ok(None));
+
+        // a moves to a new value, freeing 1
+        assert_eq!(ids.insert(a, 3), Ok(Some(1)));
+        assert_eq!(ids.handle_with(&1), None);
+        assert_eq!(ids.insert(b, 1), Ok(Some(2)));
+        assert_eq!(ids.handle_with(&1), Some(b));
+        assert!(!ids.contains_value(&2));
+
+        ids.retain(|id, _| id != b);
+        assert_eq!(ids.handle_with(&1), None);
+        assert_eq!(ids.remove(a), Some(3));
+        assert!(ids.is_empty());
+        assert!(!ids.contains_value(&3));
+    }
+}
@@ -1,4 +1,6 @@
-use crate::{EntityBorrowFromWorldTrait, EntityHandle, EntityInsertIntoWorldTrait};
+use crate::{
+    EntityBorrowFromWorldTrait, EntityHandle, EntityHandleCounter, EntityInsertIntoWorldTrait,
+};
 
 pub trait WorldTrait: WorldBorrowTrait<'static> {
     fn delete_entity(&mut self, entity: EntityHandle);
@@ -16,6 +18,8 @@ pub trait WorldTrait: WorldBorrowTrait<'static> {
 pub trait WorldBorrowTrait<'a>: Sized {
     fn new_entity(&mut self) -> EntityHandle;
     fn is_entity_already_freed(&self, handle: EntityHandle) -> bool;
+    /// The entity handles, for `Entities::live` and the like
+    fn handles(&self) -> &EntityHandleCounter;
     fn borrow_entity<T: EntityBorrowFromWorldTrait<'a, Self>>(
         &'a mut self,
         entity_handle: EntityHandle,
@@ -0,0 +1,102 @@
+use vec_ecs::comp_iter::Optional;
+use vec_ecs::{CompIter, CompVec, EntityHandleCounter};
+
+#[test]
+fn test_sixteen_comps() {
+    let mut handles = EntityHandleCounter::default();
+    let ids: Vec<_> = (0..100).map(|_| handles.next_handle()).collect();
+    let mut comps: Vec<CompVec<usize>> = (0..16).map(|_| CompVec::default()).collect();
+    for (i, comp) in comps.iter_mut().enumerate() {
+        // entity 0 has every component, entity k only the first k
+        for (k, id) in ids.iter().enumerate() {
+            if k == 0 || i < k {
+                comp.insert(*id, i);
+            }
+        }
+    }
+    let [
+        c1,
+        c2,
+        c3,
+        c4,
+        c5,
+        c6,
+        c7,
+        c8,
+        c9,
+        c10,
+        c11,
+        c12,
+        c13,
+        c14,
+        c15,
+        c16,
+    ] = &mut comps[..]
+    else {
+        unreachable!()
+    };
+    let all: Vec<_> = CompIter::from((
+        c1.iter(),
+        c2.iter(),
+        c3.iter(),
+        c4.iter(),
+        c5.iter(),
+        c6.iter(),
+        c7.iter(),
+        c8.iter(),
+        c9.iter(),
+        c10.iter(),
+        c11.iter(),
+        c12.iter(),
+        c13.iter(),
+        c14.iter(),
+        c15.iter(),
+        c16.iter_mut(),
+    ))
+    .into_iter()
+    .map(|(id, ..)| id)
+    .collect();
+    // entities 16.. have the 16 components too
+    assert_eq!(all.len(), 1 + 100 - 16);
+    assert_eq!(all[0], ids[0]);
+    assert_eq!(all[1], ids[16]);
+}
+
+#[test]
+fn test_nested() {
+    let mut handles = EntityHandleCounter::default();
+    let mut pos = CompVec::<i32>::default();
+    let mut vel = CompVec::<i32>::default();
+    let mut mass = CompVec::<i32>::default();
+    let mut name = CompVec::<&str>::default();
+    let a = handles.next_handle();
+    let b = handles.next_handle();
+    let c = handles.next_handle();
+    for id in [a, b, c] {
+        pos.insert(id, 0);
+        vel.insert(id, 1);
+    }
+    mass.insert(a, 2);
+    mass.insert(c, 3);
+    name.insert(c, "c");
+
+    for (_id, (pos, vel), (mass, name)) in CompIter::from((
+        (pos.iter_mut(), vel.iter()),
+        (mass.iter(), name.iter().optional()),
+    )) {
+        *pos += vel * mass;
+        if name.is_some() {
+            *pos = -*pos;
+        }
+    }
+    assert_eq!(pos.get(a), Some(&2));
+    assert_eq!(pos.get(b), Some(&0));
+    assert_eq!(pos.get(c), Some(&-3));
+
+    // an optional group only has components for the entities having all of them
+    let groups: Vec<_> = CompIter::from((pos.iter(), Optional::new((mass.iter(), name.iter()))))
+        .into_iter()
+        .map(|(id, _, group)| (id, group.is_some()))
+        .collect();
+    assert_eq!(groups, vec![(a, false), (b, false), (c, true)]);
+}
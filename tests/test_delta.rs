@@ -0,0 +1,147 @@
+use vec_ecs::{
+    CompVec, DeltaError, EntityHandleCounter, UniqueCompVec, WorldBorrowTrait, WorldTrait,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Position(f32, f32);
+
+#[derive(vec_ecs::World, Default, Debug, Clone, PartialEq)]
+#[world(delta, derive(PartialEq))]
+pub struct World {
+    #[world(handles)]
+    handles: EntityHandleCounter,
+    pub pos: CompVec<Position>,
+    pub names: UniqueCompVec<String>,
+}
+
+#[test]
+fn test_world_delta() {
+    let mut server = World::default();
+    let ids: Vec<_> = (0..100)
+        .map(|i| {
+            let id = server.new_entity();
+            server.pos.insert(id, Position(i as f32, 0.0));
+            server.names.insert(id, format!("e{i}")).unwrap();
+            id
+        })
+        .collect();
+    let mut client = World::default();
+    client.apply_delta(World::default().delta(&server)).unwrap();
+    assert_eq!(client, server);
+
+    let acked = server.clone();
+    for id in &ids[..10] {
+        server.delete_entity(*id);
+    }
+    for (_id, pos) in server.pos.iter_mut().step_by(3) {
+        pos.1 += 1.0;
+    }
+    // swap unique names
+    server.names.remove(ids[20]);
+    server.names.insert(ids[21], "e20".to_string()).unwrap();
+    server.names.insert(ids[20], "e21".to_string()).unwrap();
+    let spawned = server.new_entity();
+    server.pos.insert(spawned, Position(-1.0, -1.0));
+
+    let delta = acked.delta(&server);
+    assert_eq!(delta.handles.despawned, &ids[..10]);
+    assert_eq!(delta.handles.spawned, vec![spawned]);
+    assert_eq!(delta.pos.removed.len(), 10);
+    assert_eq!(delta.pos.inserted, vec![(spawned, Position(-1.0, -1.0))]);
+    assert_eq!(delta.pos.changed.len(), 30);
+    assert_eq!(delta.names.changed.len(), 2);
+
+    client.apply_delta(delta.clone()).unwrap();
+    assert_eq!(client, server);
+    // applying it again finds the despawned entities gone
+    assert_eq!(client.apply_delta(delta), Err(DeltaError::NotFound(ids[0])));
+    assert_eq!(client, server);
+    assert!(acked.delta(&acked).pos.is_empty());
+    // the client gives out the same handles as the server
+    assert_eq!(client.new_entity(), server.new_entity());
+}
+
+#[test]
+fn test_malformed_delta() {
+    let mut server = World::default();
+    let ids: Vec<_> = (0..10)
+        .map(|i| {
+            let id = server.new_entity();
+            server.pos.insert(id, Position(i as f32, 0.0));
+            server.names.insert(id, format!("e{i}")).unwrap();
+            id
+        })
+        .collect();
+    let client = server.clone();
+    server.delete_entity(ids[9]);
+    let spawned = server.new_entity();
+    server.names.insert(spawned, "new".to_string()).unwrap();
+    let delta = client.delta(&server);
+
+    let check = |delta: WorldDelta| {
+        let mut applied = client.clone();
+        let result = applied.apply_delta(delta);
+        if result.is_err() {
+            // nothing is applied when a part of the delta is wrong
+            assert_eq!(applied, client);
+        }
+        result
+    };
+
+    let mut not_unique = delta.clone();
+    not_unique.names.inserted[0].1 = "e3".to_string();
+    assert_eq!(check(not_unique), Err(DeltaError::NotUnique(spawned)));
+
+    let mut unsorted = delta.clone();
+    unsorted.pos.changed = vec![(ids[2], Position(0.0, 0.0)), (ids[1], Position(0.0, 0.0))];
+    assert_eq!(check(unsorted), Err(DeltaError::Unsorted));
+
+    let mut already_present = delta.clone();
+    already_present.pos.inserted = vec![(ids[1], Position(0.0, 0.0))];
+    assert_eq!(
+        check(already_present),
+        Err(DeltaError::AlreadyPresent(ids[1]))
+    );
+
+    let mut stale = delta.clone();
+    stale.pos.changed = vec![(spawned, Position(0.0, 0.0))];
+    assert_eq!(check(stale), Err(DeltaError::NotFound(spawned)));
+
+    let mut live_spawned = delta.clone();
+    live_spawned.handles.spawned.push(ids[0]);
+    assert_eq!(check(live_spawned), Err(DeltaError::AlreadyPresent(ids[0])));
+
+    // the entity is despawned by the delta
+    let mut dead_inserted = delta.clone();
+    dead_inserted.pos.inserted = vec![(ids[9], Position(0.0, 0.0))];
+    assert_eq!(check(dead_inserted), Err(DeltaError::InvalidHandle(ids[9])));
+
+    assert_eq!(check(delta), Ok(()));
+}
+
+#[test]
+fn test_delta_freed_indices() {
+    let mut server = World::default();
+    let first = server.new_entity();
+    let client = server.clone();
+    // indices given out and freed again before the delta are listed in it too
+    let spawned: Vec<_> = (0..5).map(|_| server.new_entity()).collect();
+    server.delete_entity(spawned[1]);
+    server.delete_entity(spawned[3]);
+    server.pos.insert(first, Position(1.0, 0.0));
+
+    let mut applied = client.clone();
+    applied.apply_delta(client.delta(&server)).unwrap();
+    assert_eq!(applied, server);
+    assert_eq!(applied.new_entity(), server.new_entity());
+
+    // a new entity past the end of the newer counter
+    let mut server = client.clone();
+    let spawned = server.new_entity();
+    let mut delta = client.delta(&server);
+    delta.handles.spawned.clear();
+    assert_eq!(
+        client.clone().apply_delta(delta),
+        Err(DeltaError::InvalidIndex(spawned.index()))
+    );
+}
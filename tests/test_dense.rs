@@ -0,0 +1,49 @@
+use vec_ecs::{CompIter, CompVec, DenseCompVec, EntityHandleCounter, WorldTrait};
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Position(usize);
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Health(usize);
+
+#[derive(vec_ecs::World, Default)]
+pub struct World {
+    #[world(handles)]
+    handles: EntityHandleCounter,
+    pub pos: DenseCompVec<Position>,
+    pub health: CompVec<Health>,
+}
+
+#[derive(vec_ecs::Entity)]
+#[entity(insert = World)]
+pub struct Unit {
+    pos: Position,
+    health: Health,
+}
+
+#[test]
+fn test_dense_in_world() {
+    let mut world = World::default();
+    let es: Vec<_> = (0..4)
+        .map(|i| {
+            world.insert(Unit {
+                pos: Position(i),
+                health: Health(10),
+            })
+        })
+        .collect();
+
+    world.delete_entity(es[1]);
+    assert_eq!(world.pos.get(es[1]), None);
+
+    for (_id, pos, health) in CompIter::from((world.pos.iter_mut(), world.health.iter())) {
+        pos.0 += health.0;
+    }
+    let all: Vec<_> = world.pos.iter().map(|(id, pos)| (id, pos.0)).collect();
+    assert_eq!(all, vec![(es[0], 10), (es[2], 12), (es[3], 13)]);
+
+    for e in es {
+        world.delete_entity(e);
+    }
+    assert!(world.is_empty());
+}
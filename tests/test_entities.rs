@@ -0,0 +1,112 @@
+use fixedbitset::FixedBitSet;
+use vec_ecs::{
+    CompIter, CompStorage, CompVec, Entities, EntityHandleCounter, SparseCompVec, TagVec,
+    WorldBorrowTrait, WorldTrait,
+};
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Position(usize);
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Timer(usize);
+
+#[derive(vec_ecs::World, Default)]
+#[world(borrow = WorldNoPos)]
+pub struct World {
+    #[world(handles)]
+    handles: EntityHandleCounter,
+    #[world(not_in = WorldNoPos)]
+    pub pos: CompVec<Position>,
+    pub timers: SparseCompVec<Timer>,
+    pub frozen: TagVec,
+}
+
+/// Six entities, the even ones with a position, the multiples of three with a timer,
+/// then the fifth one deleted
+fn world() -> (World, Vec<vec_ecs::EntityHandle>) {
+    let mut world = World::default();
+    let es: Vec<_> = (0..6)
+        .map(|i| {
+            let e = world.new_entity();
+            if i % 2 == 0 {
+                world.pos.insert(e, Position(i));
+            }
+            if i % 3 == 0 {
+                world.timers.insert(e, Timer(i));
+            }
+            e
+        })
+        .collect();
+    world.delete_entity(es[4]);
+    (world, es)
+}
+
+#[test]
+fn test_live_entities() {
+    let (mut world, es) = world();
+    let all: Vec<_> = CompIter::from((
+        Entities::live(world.handles()),
+        world.pos.iter_mut().optional(),
+    ))
+    .into_iter()
+    .map(|(id, (), pos)| (id, pos.map(|pos| pos.0)))
+    .collect();
+    assert_eq!(
+        all,
+        vec![
+            (es[0], Some(0)),
+            (es[1], None),
+            (es[2], Some(2)),
+            (es[3], None),
+            (es[5], None),
+        ]
+    );
+
+    // a respawned entity gets the handle of the new generation
+    let e = world.new_entity();
+    world.frozen.insert(es[1]);
+    let unfrozen: Vec<_> = CompIter::from((Entities::live(world.handles()),))
+        .without(&world.frozen)
+        .into_iter()
+        .map(|(id, ())| id)
+        .collect();
+    assert_eq!(unfrozen, vec![es[0], es[2], es[3], e, es[5]]);
+    assert_ne!(e, es[4]);
+}
+
+#[test]
+fn test_any_of() {
+    let (mut world, es) = world();
+    let mut found = vec![];
+    for (id, (), pos, timer) in CompIter::from((
+        Entities::any_of(
+            world.handles(),
+            &[&world.pos as &dyn CompStorage, &world.timers],
+        ),
+        world.pos.iter().optional(),
+        world.timers.iter_mut().optional(),
+    )) {
+        if let Some(timer) = timer {
+            timer.0 += pos.map_or(10, |pos| pos.0);
+        }
+        found.push(id);
+    }
+    assert_eq!(found, vec![es[0], es[2], es[3]]);
+    assert_eq!(world.timers.get(es[0]), Some(&Timer(0)));
+    assert_eq!(world.timers.get(es[3]), Some(&Timer(13)));
+}
+
+#[test]
+fn test_in_set() {
+    let (mut world, es) = world();
+    let mut set = FixedBitSet::with_capacity(10);
+    // the deleted entity and an index that was never given out are skipped
+    set.insert_range(3..10);
+    let (_pos, rest) = world.split_world_no_pos();
+    let found: Vec<_> =
+        CompIter::from((Entities::in_set(rest.handles(), &set), rest.timers.iter()))
+            .into_iter()
+            .map(|(id, (), timer)| (id, timer.0))
+            .collect();
+    assert_eq!(found, vec![(es[3], 3)]);
+}
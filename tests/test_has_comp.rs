@@ -0,0 +1,76 @@
+use vec_ecs::{CompVec, EntityHandleCounter, HasComp, WorldBorrowTrait, WorldQueryTrait};
+
+#[derive(Debug, PartialEq)]
+pub struct Position(f32, f32);
+
+#[derive(Debug, PartialEq)]
+pub struct Velocity(f32, f32);
+
+#[derive(Debug, PartialEq)]
+pub struct Flag;
+
+#[derive(vec_ecs::World, Default)]
+#[world(borrow = WorldNoFlag)]
+pub struct World {
+    #[world(handles)]
+    handles: EntityHandleCounter,
+    pub pos: CompVec<Position>,
+    pub vel: CompVec<Velocity>,
+    #[world(not_in = WorldNoFlag)]
+    pub flag: CompVec<Flag>,
+    // two fields with the same component type don't get `HasComp`
+    pub a: CompVec<()>,
+    pub b: CompVec<()>,
+    // the same type written differently needs to be left out
+    #[world(no_has_comp)]
+    pub other_vel: CompVec<crate::Velocity>,
+}
+
+/// Written once for every world and borrow struct with positions and velocities
+fn integrate(world: &mut (impl HasComp<Position> + HasComp<Velocity>)) {
+    for (_id, (pos, vel)) in world.query::<(&mut Position, &Velocity)>() {
+        pos.0 += vel.0;
+        pos.1 += vel.1;
+    }
+}
+
+fn count_positions(world: &impl HasComp<Position>) -> usize {
+    world.comps().len()
+}
+
+#[test]
+fn test_has_comp() {
+    let mut world = World::default();
+    let a = world.new_entity();
+    world.pos.insert(a, Position(0.0, 0.0));
+    world.vel.insert(a, Velocity(1.0, 2.0));
+    world.flag.insert(a, Flag);
+    let b = world.new_entity();
+    world.pos.insert(b, Position(0.0, 0.0));
+
+    integrate(&mut world);
+    let (_flag, mut world_no_flag) = world.split_world_no_flag();
+    integrate(&mut world_no_flag);
+    assert_eq!(count_positions(&world_no_flag), 2);
+    assert_eq!(world.pos.get(a), Some(&Position(2.0, 4.0)));
+    assert_eq!(world.pos.get(b), Some(&Position(0.0, 0.0)));
+
+    let queried: Vec<_> = world
+        .query::<(&Position, Option<&mut Velocity>, Option<&Flag>)>()
+        .map(|(id, (pos, vel, flag))| (id, pos.0, vel.is_some(), flag.is_some()))
+        .collect();
+    assert_eq!(queried, vec![(a, 2.0, true, true), (b, 0.0, false, false)]);
+
+    HasComp::<Velocity>::comps_mut(&mut world).remove(a);
+    assert_eq!(world.query::<(&Position, &Velocity)>().count(), 0);
+
+    world.other_vel.insert(b, Velocity(0.0, 0.0));
+    assert_eq!(HasComp::<Velocity>::comps(&world).len(), 0);
+}
+
+#[test]
+#[should_panic(expected = "is in the query twice")]
+fn test_query_twice() {
+    let mut world = World::default();
+    world.query::<(&mut Position, &Position)>().count();
+}
@@ -0,0 +1,69 @@
+use std::sync::{Arc, Mutex};
+use vec_ecs::{
+    CompVec, ComponentHooks, EntityHandle, EntityHandleCounter, WorldBorrowTrait, WorldTrait,
+};
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Body(u32);
+
+#[derive(vec_ecs::World, Default)]
+pub struct World {
+    #[world(handles)]
+    handles: EntityHandleCounter,
+    pub bodies: CompVec<Body>,
+}
+
+#[derive(Debug, PartialEq)]
+enum Event {
+    Insert(EntityHandle, u32),
+    Replace(EntityHandle, u32),
+    Remove(EntityHandle, u32),
+}
+
+#[test]
+fn test_hooks() {
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let (on_insert, on_replace, on_remove) = (events.clone(), events.clone(), events.clone());
+    let mut world = World::default();
+    world.bodies.set_hooks(
+        ComponentHooks::default()
+            .on_insert(move |id, body: &Body| {
+                on_insert.lock().unwrap().push(Event::Insert(id, body.0))
+            })
+            .on_replace(move |id, body: &Body| {
+                on_replace.lock().unwrap().push(Event::Replace(id, body.0))
+            })
+            .on_remove(move |id, body: &Body| {
+                on_remove.lock().unwrap().push(Event::Remove(id, body.0))
+            }),
+    );
+
+    let e0 = world.new_entity();
+    let e1 = world.new_entity();
+    let e2 = world.new_entity();
+    world.bodies.insert(e0, Body(0));
+    world.bodies.insert(e0, Body(10));
+    world
+        .bodies
+        .insert_many([(e1, Body(1)), (e2, Body(2)), (e1, Body(11))]);
+    *world.bodies.entry(e1).or_insert(Body(100)) = Body(21);
+    world.bodies.remove(e0);
+    world.delete_entity(e1);
+    world.bodies.retain(|_, body| body.0 != 2);
+    world.delete_entity(e2);
+
+    assert_eq!(
+        std::mem::take(&mut *events.lock().unwrap()),
+        vec![
+            Event::Insert(e0, 0),
+            Event::Replace(e0, 0),
+            // insert_many goes through the batch in entity index order
+            Event::Insert(e1, 1),
+            Event::Replace(e1, 1),
+            Event::Insert(e2, 2),
+            Event::Remove(e0, 10),
+            Event::Remove(e1, 21),
+            Event::Remove(e2, 2),
+        ]
+    );
+}
@@ -0,0 +1,51 @@
+use vec_ecs::{
+    CompIter, CompVec, EntityHandleCounter, IndexedCompVec, WorldBorrowTrait, WorldTrait,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Name(String);
+
+#[derive(Debug)]
+pub struct Health(u32);
+
+#[derive(vec_ecs::World, Default)]
+pub struct World {
+    #[world(handles)]
+    handles: EntityHandleCounter,
+    pub names: IndexedCompVec<Name>,
+    pub health: CompVec<Health>,
+}
+
+#[test]
+fn test_indexed_world() {
+    let mut world = World::default();
+    let mut spawn = |name: &str, health| {
+        let id = world.new_entity();
+        world.names.insert(id, Name(name.to_string()));
+        world.health.insert(id, Health(health));
+        id
+    };
+    let a = spawn("goblin", 5);
+    let b = spawn("goblin", 0);
+    let c = spawn("orc", 10);
+
+    let goblin = Name("goblin".to_string());
+    assert_eq!(world.names.handles_with(&goblin).len(), 2);
+
+    for (_id, mut name, health) in CompIter::from((world.names.iter_mut(), world.health.iter())) {
+        if health.0 == 0 {
+            name.0.push_str(" corpse");
+        }
+    }
+    assert_eq!(world.names.handles_with(&goblin), &[a]);
+    assert_eq!(
+        world.names.handles_with(&Name("goblin corpse".to_string())),
+        &[b]
+    );
+
+    world.delete_entity(a);
+    world.delete_entity(c);
+    assert!(!world.names.contains_value(&goblin));
+    assert!(!world.names.contains_value(&Name("orc".to_string())));
+    assert_eq!(world.names.len(), 1);
+}
@@ -0,0 +1,79 @@
+use vec_ecs::{CompVec, EntityHandleCounter, TagVec, WorldBorrowTrait, WorldQueryTrait};
+
+#[derive(Debug, PartialEq)]
+pub struct Position(f32, f32);
+
+#[derive(Debug, PartialEq)]
+pub struct Velocity(f32, f32);
+
+#[derive(vec_ecs::World, Default)]
+#[world(borrow = WorldNoPos)]
+pub struct World {
+    #[world(handles)]
+    handles: EntityHandleCounter,
+    #[world(not_in = WorldNoPos)]
+    pub pos: CompVec<Position>,
+    pub vel: CompVec<Velocity>,
+    pub mass: CompVec<f32>,
+    pub name: CompVec<&'static str>,
+    pub frozen: TagVec,
+}
+
+#[derive(vec_ecs::Query)]
+#[query(world = World)]
+#[query(without = frozen)]
+pub struct Movers<'a> {
+    name: Option<&'a &'static str>,
+    pos: &'a mut Position,
+    vel: &'a Velocity,
+    mass: Option<&'a mut f32>,
+}
+
+#[derive(vec_ecs::Query)]
+#[query(world = World, borrow = WorldNoPos)]
+#[query(with = frozen)]
+pub struct Frozen<'w> {
+    vel: &'w mut Velocity,
+}
+
+#[test]
+fn test_query() {
+    let mut world = World::default();
+    let ids: Vec<_> = (0..4)
+        .map(|i| {
+            let id = world.new_entity();
+            world.pos.insert(id, Position(0.0, 0.0));
+            world.vel.insert(id, Velocity(i as f32, 1.0));
+            id
+        })
+        .collect();
+    world.mass.insert(ids[1], 2.0);
+    world.name.insert(ids[2], "named");
+    world.frozen.insert(ids[3]);
+
+    let mut moved = Vec::new();
+    for (id, movers) in world.query::<Movers>() {
+        let mass = movers.mass.map_or(1.0, |mass| {
+            *mass *= 2.0;
+            *mass
+        });
+        movers.pos.0 += movers.vel.0 * mass;
+        movers.pos.1 += movers.vel.1 * mass;
+        moved.push((id, movers.name.copied()));
+    }
+    assert_eq!(
+        moved,
+        vec![(ids[0], None), (ids[1], None), (ids[2], Some("named"))]
+    );
+    assert_eq!(world.pos.get(ids[1]), Some(&Position(4.0, 4.0)));
+    assert_eq!(world.pos.get(ids[3]), Some(&Position(0.0, 0.0)));
+    assert_eq!(world.mass.get(ids[1]), Some(&4.0));
+
+    let (pos, mut world_no_pos) = world.split_world_no_pos();
+    for (id, frozen) in world_no_pos.query::<Frozen>() {
+        assert_eq!(pos.get(id), Some(&Position(0.0, 0.0)));
+        frozen.vel.0 = 0.0;
+    }
+    assert_eq!(world.vel.get(ids[3]), Some(&Velocity(0.0, 1.0)));
+    assert_eq!(world.query::<Frozen>().count(), 1);
+}
@@ -0,0 +1,85 @@
+use vec_ecs::{
+    CompIter, CompVec, EntityHandleCounter, RollbackBuffer, TagVec, WorldBorrowTrait, WorldTrait,
+};
+
+#[derive(vec_ecs::World, Default, Debug, Clone)]
+#[world(rollback)]
+pub struct World {
+    #[world(handles)]
+    handles: EntityHandleCounter,
+    pub pos: CompVec<i64>,
+    pub vel: CompVec<i64>,
+    pub frozen: TagVec,
+}
+
+/// Moves the entities, despawns the ones past 100 and spawns one every 3 ticks
+fn simulate(world: &mut World, tick: u64) {
+    for (_id, pos, vel) in
+        CompIter::from((world.pos.iter_mut(), world.vel.iter())).without(&world.frozen)
+    {
+        *pos += vel;
+    }
+    let out: Vec<_> = world
+        .pos
+        .iter()
+        .filter(|(_, pos)| **pos > 100)
+        .map(|(id, _)| id)
+        .collect();
+    for id in out {
+        world.delete_entity(id);
+    }
+    if tick.is_multiple_of(3) {
+        let id = world.new_entity();
+        world.pos.insert(id, 0);
+        world.vel.insert(id, tick as i64 % 7 + 1);
+    }
+}
+
+fn dump(world: &World) -> Vec<(vec_ecs::EntityHandle, i64)> {
+    world.pos.iter().map(|(id, pos)| (id, *pos)).collect()
+}
+
+#[test]
+fn test_rollback() {
+    let mut world = World::default();
+    let mut rollback = RollbackBuffer::new(16);
+    let frozen = world.new_entity();
+    world.pos.insert(frozen, 0);
+    world.vel.insert(frozen, 1);
+    world.frozen.insert(frozen);
+
+    let mut states = Vec::new();
+    for tick in 0..40 {
+        simulate(&mut world, tick);
+        rollback.save(&mut world, tick);
+        states.push(world.clone());
+    }
+    assert_eq!(rollback.len(), 16);
+    assert_eq!(rollback.ticks().next(), Some(24));
+    assert!(!rollback.restore(&mut world, 10));
+
+    assert!(rollback.restore(&mut world, 30));
+    assert_eq!(dump(&world), dump(&states[30]));
+    // re-simulating gives the same entities and handles
+    for tick in 31..40 {
+        simulate(&mut world, tick);
+        rollback.save(&mut world, tick);
+        assert_eq!(dump(&world), dump(&states[tick as usize]));
+    }
+    assert_eq!(world.new_entity(), states[39].clone().new_entity());
+
+    // the rolled back entity gets its handle back
+    assert!(rollback.restore(&mut world, 33));
+    let spawned = world.new_entity();
+    assert!(rollback.restore(&mut world, 33));
+    assert_eq!(world.new_entity(), spawned);
+
+    rollback.save(&mut world, 34);
+    assert_eq!(rollback.ticks().last(), Some(34));
+    rollback.discard_before(30);
+    assert_eq!(
+        rollback.ticks().collect::<Vec<_>>(),
+        vec![30, 31, 32, 33, 34]
+    );
+    assert!(rollback.contains(33));
+}
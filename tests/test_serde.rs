@@ -0,0 +1,111 @@
+#![cfg(feature = "serde")]
+
+use vec_ecs::serde::{Deserialize, Serialize};
+use vec_ecs::{CompVec, EntityHandleCounter, UniqueCompVec, WorldBorrowTrait, WorldTrait};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(crate = "vec_ecs::serde")]
+pub struct Position(f32, f32);
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(crate = "vec_ecs::serde")]
+pub struct Name(String);
+
+#[derive(vec_ecs::World, Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(crate = "vec_ecs::serde")]
+#[world(delta, derive(PartialEq, Serialize, Deserialize))]
+pub struct World {
+    #[world(handles)]
+    handles: EntityHandleCounter,
+    pub pos: CompVec<Position>,
+    pub names: UniqueCompVec<Name>,
+}
+
+#[test]
+fn test_world_roundtrip() {
+    let mut world = World::default();
+    for i in 0..300 {
+        let id = world.new_entity();
+        world.pos.insert(id, Position(i as f32, 0.0));
+        if i % 7 == 0 {
+            world.names.insert(id, Name(format!("e{i}"))).unwrap();
+        }
+    }
+    let deleted = world.new_entity();
+    world.pos.insert(deleted, Position(0.0, 0.0));
+    world.delete_entity(deleted);
+
+    let json = serde_json::to_string(&world).unwrap();
+    let mut loaded: World = serde_json::from_str(&json).unwrap();
+    assert_eq!(world, loaded);
+    assert_eq!(
+        loaded
+            .names
+            .handle_with(&Name("e14".to_string()))
+            .map(|id| id.index()),
+        Some(14)
+    );
+    // the free list and generations are kept
+    assert_eq!(world.new_entity(), loaded.new_entity());
+    assert_eq!(world.new_entity(), loaded.new_entity());
+
+    let acked = world.clone();
+    let id = world.new_entity();
+    world.names.insert(id, Name("new".to_string())).unwrap();
+    let delta = serde_json::to_string(&acked.delta(&world)).unwrap();
+    loaded
+        .apply_delta(serde_json::from_str(&delta).unwrap())
+        .unwrap();
+    assert_eq!(world, loaded);
+}
+
+#[test]
+fn test_reject_huge_delta() {
+    let mut world = World::default();
+    let id = world.new_entity();
+    let mut newer = world.clone();
+    newer.pos.insert(id, Position(0.0, 0.0));
+    let delta = serde_json::to_string(&world.delta(&newer)).unwrap();
+
+    // `next` past the indices the delta lists errors instead of allocating
+    let huge = delta.replace(r#""next":1"#, &format!(r#""next":{}"#, 1u64 << 50));
+    assert_ne!(huge, delta);
+    let err = world.apply_delta(serde_json::from_str(&huge).unwrap());
+    assert_eq!(err, Err(vec_ecs::DeltaError::InvalidIndex(1)));
+
+    // an inserted component far past the live entities
+    let far = delta.replace(
+        r#"{"index":0,"generation":0}"#,
+        &format!(r#"{{"index":{},"generation":0}}"#, 1u64 << 50),
+    );
+    let err = world.apply_delta(serde_json::from_str(&far).unwrap());
+    assert!(matches!(err, Err(vec_ecs::DeltaError::InvalidHandle(_))));
+    assert_eq!(world.pos.len(), 0);
+}
+
+#[test]
+fn test_reject_inconsistent() {
+    let comps = |owners: &str, comps: &str| {
+        serde_json::from_str::<CompVec<u32>>(&format!(r#"{{"owners":{owners},"comps":{comps}}}"#))
+    };
+    let e0 = r#"{"index":0,"generation":0}"#;
+    let e1 = r#"{"index":1,"generation":0}"#;
+    let ok = comps(r#"{"len":2,"words":[3]}"#, &format!("[[{e0},5],[{e1},6]]")).unwrap();
+    assert_eq!(ok.len(), 2);
+    // owner missing
+    assert!(comps(r#"{"len":2,"words":[1]}"#, &format!("[[{e0},5],[{e1},6]]")).is_err());
+    // bit past the length
+    assert!(comps(r#"{"len":1,"words":[3]}"#, &format!("[[{e0},5],[{e1},6]]")).is_err());
+    // not sorted by index
+    assert!(comps(r#"{"len":2,"words":[3]}"#, &format!("[[{e1},5],[{e0},6]]")).is_err());
+
+    let counter = |json: &str| serde_json::from_str::<EntityHandleCounter>(json);
+    assert!(counter(r#"{"next":2,"generation":1,"removed":false,"free":{"len":2,"words":[2]},"generation_to_free":[1,null]}"#).is_ok());
+    // index 1 neither alive nor free
+    assert!(counter(r#"{"next":2,"generation":1,"removed":false,"free":{"len":2,"words":[0]},"generation_to_free":[1,null]}"#).is_err());
+    // missing generation
+    assert!(counter(r#"{"next":2,"generation":1,"removed":false,"free":{"len":0,"words":[]},"generation_to_free":[1]}"#).is_err());
+
+    let names = format!(r#"{{"owners":{{"len":2,"words":[3]}},"comps":[[{e0},"a"],[{e1},"a"]]}}"#);
+    assert!(serde_json::from_str::<UniqueCompVec<String>>(&names).is_err());
+}
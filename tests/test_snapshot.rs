@@ -0,0 +1,153 @@
+use std::io::{self, Read, Write};
+use vec_ecs::{
+    CompVec, EntityHandle, EntityHandleCounter, SnapshotComponent, SnapshotError, TagVec,
+    TypeHasher, UniqueCompVec, WorldBorrowTrait, WorldTrait,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Position(f32, f32);
+
+impl SnapshotComponent for Position {
+    fn write_snapshot(&self, writer: &mut impl Write) -> io::Result<()> {
+        self.0.write_snapshot(writer)?;
+        self.1.write_snapshot(writer)
+    }
+
+    fn read_snapshot(reader: &mut impl Read) -> io::Result<Self> {
+        Ok(Position(
+            f32::read_snapshot(reader)?,
+            f32::read_snapshot(reader)?,
+        ))
+    }
+
+    fn type_hash() -> u64 {
+        TypeHasher::default().bytes(b"Position").finish()
+    }
+}
+
+#[derive(vec_ecs::World, Default, Debug)]
+#[world(snapshot)]
+pub struct World {
+    #[world(handles)]
+    handles: EntityHandleCounter,
+    pub pos: CompVec<Position>,
+    pub names: UniqueCompVec<String>,
+    pub targets: CompVec<EntityHandle>,
+    pub frozen: TagVec,
+}
+
+#[derive(vec_ecs::World, Default, Debug)]
+#[world(snapshot)]
+pub struct WorldNoTargets {
+    #[world(handles)]
+    handles: EntityHandleCounter,
+    pub pos: CompVec<Position>,
+    pub names: UniqueCompVec<String>,
+    pub frozen: TagVec,
+}
+
+#[derive(vec_ecs::World, Default, Debug)]
+#[world(snapshot)]
+pub struct WorldOtherPos {
+    #[world(handles)]
+    handles: EntityHandleCounter,
+    pub pos: CompVec<(f64, f64)>,
+    pub names: UniqueCompVec<String>,
+    pub targets: CompVec<EntityHandle>,
+    pub frozen: TagVec,
+}
+
+fn make_world() -> World {
+    let mut world = World::default();
+    let mut prev = None;
+    for i in 0..500 {
+        let id = world.new_entity();
+        world.pos.insert(id, Position(i as f32, -(i as f32)));
+        if i % 10 == 0 {
+            world.names.insert(id, format!("e{i}")).unwrap();
+        }
+        if let Some(prev) = prev {
+            world.targets.insert(id, prev);
+        }
+        if i % 4 == 0 {
+            world.frozen.insert(id);
+        }
+        prev = Some(id);
+    }
+    let deleted: Vec<_> = world.pos.iter().step_by(7).map(|(id, _)| id).collect();
+    for id in deleted {
+        world.delete_entity(id);
+    }
+    world
+}
+
+#[test]
+fn test_snapshot_roundtrip() {
+    let mut world = make_world();
+    let mut bytes = Vec::new();
+    world.write_snapshot(&mut bytes).unwrap();
+    let mut loaded = World::read_snapshot(bytes.as_slice()).unwrap();
+
+    assert_eq!(world.pos, loaded.pos);
+    assert_eq!(world.names, loaded.names);
+    assert_eq!(world.targets, loaded.targets);
+    assert!(world.frozen.iter().eq(loaded.frozen.iter()));
+    // the free list and generations are kept
+    for _ in 0..100 {
+        assert_eq!(world.new_entity(), loaded.new_entity());
+    }
+}
+
+#[test]
+fn test_snapshot_rejects_mismatch() {
+    let world = make_world();
+    let mut bytes = Vec::new();
+    world.write_snapshot(&mut bytes).unwrap();
+
+    let err = WorldNoTargets::read_snapshot(bytes.as_slice()).unwrap_err();
+    assert!(matches!(err, SnapshotError::SchemaMismatch(_)), "{err}");
+    assert_eq!(
+        err.to_string(),
+        "snapshot schema mismatch: the snapshot has the fields [pos, names, targets, frozen] but the world has [pos, names, frozen]"
+    );
+    let err = WorldOtherPos::read_snapshot(bytes.as_slice()).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "snapshot schema mismatch: field `pos` has a different type in the snapshot"
+    );
+
+    let err = World::read_snapshot(&b"not a snapshot"[..]).unwrap_err();
+    assert!(matches!(err, SnapshotError::BadMagic));
+    let mut newer = bytes.clone();
+    newer[4] += 1;
+    let err = World::read_snapshot(newer.as_slice()).unwrap_err();
+    assert!(matches!(err, SnapshotError::UnsupportedVersion(2)));
+    let err = World::read_snapshot(&bytes[..bytes.len() - 1]).unwrap_err();
+    assert!(matches!(err, SnapshotError::Io(_)));
+}
+
+#[test]
+fn test_snapshot_rejects_corrupt_handles() {
+    // next, generation, removed, then an empty free list and the live generations
+    let handles = |next: usize, generations: &[u32]| {
+        let mut bytes = Vec::new();
+        next.write_snapshot(&mut bytes).unwrap();
+        1u32.write_snapshot(&mut bytes).unwrap();
+        false.write_snapshot(&mut bytes).unwrap();
+        0usize.write_snapshot(&mut bytes).unwrap();
+        for generation in generations {
+            generation.write_snapshot(&mut bytes).unwrap();
+        }
+        bytes
+    };
+
+    let counter = EntityHandleCounter::read_snapshot(&mut handles(2, &[0, 1]).as_slice()).unwrap();
+    assert_eq!(counter.handle_at(1).map(|id| id.generation()), Some(1));
+
+    // a huge `next` without the generations to back it errors instead of allocating
+    let err = EntityHandleCounter::read_snapshot(&mut handles(1 << 45, &[0]).as_slice());
+    assert!(matches!(err, Err(SnapshotError::Io(_))));
+
+    let err = EntityHandleCounter::read_snapshot(&mut handles(2, &[0, 2]).as_slice());
+    assert!(matches!(err, Err(SnapshotError::Invalid(_))));
+}
@@ -0,0 +1,60 @@
+use vec_ecs::{
+    CompIter, CompVec, EntityHandleCounter, SparseCompVec, WorldBorrowTrait, WorldTrait,
+};
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Position(usize);
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Timer(usize);
+
+#[derive(vec_ecs::World, Default)]
+pub struct World {
+    #[world(handles)]
+    handles: EntityHandleCounter,
+    pub pos: CompVec<Position>,
+    pub timers: SparseCompVec<Timer>,
+}
+
+#[test]
+fn test_sparse_in_world() {
+    let mut world = World::default();
+    let es: Vec<_> = (0..6)
+        .map(|i| {
+            let e = world.new_entity();
+            world.pos.insert(e, Position(i));
+            if i % 2 == 0 {
+                world.timers.insert(e, Timer(i));
+            }
+            e
+        })
+        .collect();
+
+    world.delete_entity(es[2]);
+    assert_eq!(world.timers.get(es[2]), None);
+    assert_eq!(world.timers.len(), 2);
+
+    let with: Vec<_> = CompIter::from((world.pos.iter(),))
+        .with(&world.timers)
+        .into_iter()
+        .map(|(id, pos)| (id, pos.0))
+        .collect();
+    assert_eq!(with, vec![(es[0], 0), (es[4], 4)]);
+
+    let without: Vec<_> = CompIter::from((world.pos.iter(),))
+        .without(&world.timers)
+        .into_iter()
+        .map(|(id, pos)| (id, pos.0))
+        .collect();
+    assert_eq!(without, vec![(es[1], 1), (es[3], 3), (es[5], 5)]);
+
+    for (_id, timer, pos) in CompIter::from((world.timers.iter_mut(), world.pos.iter())) {
+        timer.0 += pos.0;
+    }
+    assert_eq!(world.timers.get(es[4]), Some(&Timer(8)));
+
+    for e in es {
+        world.delete_entity(e);
+    }
+    assert!(world.is_empty());
+}
@@ -0,0 +1,38 @@
+use vec_ecs::{CompIter, CompVec, EntityHandleCounter, TagVec, WorldBorrowTrait, WorldTrait};
+
+#[derive(Debug, Default)]
+pub struct Position(f32);
+
+#[derive(vec_ecs::World, Default)]
+pub struct World {
+    #[world(handles)]
+    handles: EntityHandleCounter,
+    pub pos: CompVec<Position>,
+    pub frozen: TagVec,
+}
+
+#[test]
+fn test_tags_in_world() {
+    let mut world = World::default();
+    let e1 = world.new_entity();
+    world.pos.insert(e1, Position(0.0));
+    world.frozen.insert(e1);
+    let e2 = world.new_entity();
+    world.pos.insert(e2, Position(1.0));
+
+    for (_id, pos) in CompIter::from((world.pos.iter_mut(),)).without(&world.frozen) {
+        pos.0 += 1.0;
+    }
+    assert_eq!(world.pos.get(e1).unwrap().0, 0.0);
+    assert_eq!(world.pos.get(e2).unwrap().0, 2.0);
+
+    world.delete_entity(e1);
+    assert!(world.frozen.is_empty());
+    let e3 = world.new_entity();
+    assert_eq!(e3.index(), e1.index());
+    assert!(!world.frozen.contains(e3));
+
+    world.delete_entity(e2);
+    world.delete_entity(e3);
+    assert!(world.is_empty());
+}
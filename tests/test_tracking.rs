@@ -0,0 +1,45 @@
+use vec_ecs::{CompVec, EntityHandleCounter, Tracking, WorldBorrowTrait, WorldTrait};
+
+#[derive(Debug, PartialEq)]
+pub struct Body(u32);
+
+#[derive(vec_ecs::World, Default)]
+pub struct World {
+    #[world(handles)]
+    handles: EntityHandleCounter,
+    pub bodies: CompVec<Body>,
+}
+
+#[test]
+fn test_added_removed_tracking() {
+    let mut world = World::default();
+    let e0 = world.new_entity();
+    world.bodies.insert(e0, Body(0));
+    assert_eq!(world.bodies.drain_added().count(), 0);
+
+    world.bodies.set_tracking(Tracking::Values);
+    let e1 = world.new_entity();
+    let e2 = world.new_entity();
+    world.bodies.insert_many([(e1, Body(1)), (e2, Body(2))]);
+    world.bodies.insert(e1, Body(10));
+    assert_eq!(world.bodies.drain_added().collect::<Vec<_>>(), vec![e1, e2]);
+    assert_eq!(world.bodies.drain_added().count(), 0);
+
+    assert_eq!(world.bodies.remove(e0), Some(Body(0)));
+    world.delete_entity(e1);
+    world.bodies.retain(|_, body| body.0 != 2);
+    assert_eq!(
+        world.bodies.drain_removed().collect::<Vec<_>>(),
+        vec![(e0, None), (e1, Some(Body(10))), (e2, Some(Body(2)))]
+    );
+
+    world.bodies.set_tracking(Tracking::Handles);
+    let e3 = world.new_entity();
+    world.bodies.insert(e3, Body(3));
+    world.delete_entity(e3);
+    assert_eq!(world.bodies.drain_added().collect::<Vec<_>>(), vec![e3]);
+    assert_eq!(
+        world.bodies.drain_removed().collect::<Vec<_>>(),
+        vec![(e3, None)]
+    );
+}
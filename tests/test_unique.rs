@@ -0,0 +1,35 @@
+use vec_ecs::{CompVec, EntityHandleCounter, UniqueCompVec, WorldBorrowTrait, WorldTrait};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PlayerId(u64);
+
+#[derive(Debug)]
+pub struct Score(u32);
+
+#[derive(vec_ecs::World, Default)]
+pub struct World {
+    #[world(handles)]
+    handles: EntityHandleCounter,
+    pub player_ids: UniqueCompVec<PlayerId>,
+    pub scores: CompVec<Score>,
+}
+
+#[test]
+fn test_unique_world() {
+    let mut world = World::default();
+    let a = world.new_entity();
+    world.player_ids.insert(a, PlayerId(7)).unwrap();
+    world.scores.insert(a, Score(0));
+
+    let b = world.new_entity();
+    let err = world.player_ids.insert(b, PlayerId(7)).unwrap_err();
+    assert_eq!(err.existing, a);
+    assert_eq!(err.comp, PlayerId(7));
+
+    // deleting the entity frees its value
+    assert!(world.scores.get(a).is_some_and(|score| score.0 == 0));
+    world.delete_entity(a);
+    assert_eq!(world.player_ids.handle_with(&PlayerId(7)), None);
+    world.player_ids.insert(b, PlayerId(7)).unwrap();
+    assert_eq!(world.player_ids.handle_with(&PlayerId(7)), Some(b));
+}
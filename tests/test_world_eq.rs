@@ -0,0 +1,89 @@
+use std::collections::HashSet;
+use vec_ecs::{CompIter, CompVec, EntityHandleCounter, WorldBorrowTrait, WorldTrait};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Position(i32, i32);
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Velocity(i32, i32);
+
+#[derive(vec_ecs::World, Default, Debug, Clone, PartialEq, Eq, Hash)]
+#[world(borrow = WorldNoPos)]
+#[world(derive(PartialEq, Eq, Hash))]
+pub struct World {
+    #[world(handles)]
+    handles: EntityHandleCounter,
+    #[world(not_in = WorldNoPos)]
+    pub pos: CompVec<Position>,
+    pub vel: CompVec<Velocity>,
+}
+
+fn step(world: &mut World) {
+    for (_id, pos, vel) in CompIter::from((world.pos.iter_mut(), world.vel.iter())) {
+        pos.0 += vel.0;
+        pos.1 += vel.1;
+    }
+}
+
+#[test]
+fn test_world_snapshot_eq() {
+    let mut world = World::default();
+    for i in 0..10 {
+        let id = world.new_entity();
+        world.pos.insert(id, Position(i, 0));
+        if i % 3 == 0 {
+            world.vel.insert(id, Velocity(1, 1));
+        }
+    }
+    let deleted = world.new_entity();
+    world.delete_entity(deleted);
+
+    let snapshot = world.clone();
+    assert_eq!(world, snapshot);
+    step(&mut world);
+    assert_ne!(world, snapshot);
+
+    let mut world2 = snapshot.clone();
+    step(&mut world2);
+    assert_eq!(world, world2);
+    // same handles are given out after a clone
+    assert_eq!(world.new_entity(), world2.new_entity());
+
+    let set: HashSet<_> = [world.clone(), world2.clone(), snapshot.clone()].into();
+    assert_eq!(set.len(), 2);
+
+    let (_, borrow) = world.split_world_no_pos();
+    let (_, borrow2) = world2.split_world_no_pos();
+    assert_eq!(borrow, borrow2);
+}
+
+/// Spawns `spawned` entities with a position at their index, then deletes `deleted`
+/// and spawns `respawned` more
+fn world_after(spawned: usize, deleted: &[usize], respawned: usize) -> World {
+    let mut world = World::default();
+    let ids: Vec<_> = (0..spawned).map(|_| world.new_entity()).collect();
+    for id in &ids {
+        world.pos.insert(*id, Position(id.index() as i32, 0));
+    }
+    for i in deleted {
+        world.delete_entity(ids[*i]);
+    }
+    for _ in 0..respawned {
+        let id = world.new_entity();
+        world.pos.insert(id, Position(id.index() as i32, 0));
+    }
+    world
+}
+
+#[test]
+fn test_eq_after_different_histories() {
+    // index 0 reused and index 2 given out for the first time
+    let first = world_after(2, &[0], 2);
+    // indices 0 and 2 both reused, after being freed together
+    let second = world_after(3, &[0, 2], 2);
+    assert_eq!(first, second);
+    let set: HashSet<_> = [first.clone(), second.clone()].into();
+    assert_eq!(set.len(), 1);
+
+    assert_ne!(first, world_after(3, &[0], 1));
+}
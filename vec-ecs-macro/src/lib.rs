@@ -1,7 +1,7 @@
 use heck::ToSnekCase;
 use proc_macro::TokenStream;
 use quote::{format_ident, quote, quote_spanned};
-use syn::{parse_macro_input, DeriveInput, Ident};
+use syn::{DeriveInput, Ident, parse_macro_input};
 
 #[proc_macro_derive(World, attributes(world))]
 pub fn world_derive(input: TokenStream) -> TokenStream {
@@ -15,6 +15,10 @@ pub fn world_derive(input: TokenStream) -> TokenStream {
     };
 
     let mut borrow_names = Vec::new();
+    let mut derives = Vec::new();
+    let mut snapshot = false;
+    let mut delta = false;
+    let mut rollback = false;
 
     for attr in input.attrs.iter() {
         if attr.path().is_ident("world") {
@@ -25,6 +29,28 @@ pub fn world_derive(input: TokenStream) -> TokenStream {
                     let s: Ident = value.parse()?; // this parses borrow_name
                     borrow_names.push(s);
                     Ok(())
+                } else if meta.path.is_ident("delta") {
+                    delta = true;
+                    Ok(())
+                } else if meta.path.is_ident("rollback") {
+                    rollback = true;
+                    Ok(())
+                } else if meta.path.is_ident("snapshot") {
+                    snapshot = true;
+                    Ok(())
+                } else if meta.path.is_ident("derive") {
+                    // this parses `derive(PartialEq, Eq, ...)`
+                    meta.parse_nested_meta(|meta| match meta.path.get_ident() {
+                        Some(ident)
+                            if STD_DERIVES.iter().chain(SERDE_DERIVES).any(|t| ident == t) =>
+                        {
+                            derives.push(ident.clone());
+                            Ok(())
+                        }
+                        _ => Err(meta.error(
+                            "only PartialEq, Eq, Hash, Serialize and Deserialize can be derived for the generated structs, use #[derive(...)] for the world itself",
+                        )),
+                    })
                 } else {
                     Err(meta.error("unsupported attribute"))
                 }
@@ -37,6 +63,7 @@ pub fn world_derive(input: TokenStream) -> TokenStream {
 
     let mut fields_borrow_without = Vec::new();
     let mut handles_field = None;
+    let mut no_has_comp_fields = Vec::new();
 
     for field in st.fields.iter() {
         for attr in field.attrs.iter() {
@@ -45,6 +72,9 @@ pub fn world_derive(input: TokenStream) -> TokenStream {
                     if meta.path.is_ident("handles") {
                         handles_field = Some(field);
                         Ok(())
+                    } else if meta.path.is_ident("no_has_comp") {
+                        no_has_comp_fields.push(field.ident.as_ref().unwrap());
+                        Ok(())
                     } else if meta.path.is_ident("not_in") {
                         // this parses the `split_off`
                         let value = meta.value()?; // this parses the `=`
@@ -70,6 +100,27 @@ pub fn world_derive(input: TokenStream) -> TokenStream {
     let handles_name = handles_field.ident.as_ref().unwrap();
     let handle_ty = &handles_field.ty;
 
+    // `HasComp` is only implemented for the component types in a single `CompVec` field
+    // not labelled `#[world(no_has_comp)]`, the others would give conflicting impls
+    let comp_vec_fields: Vec<_> = st
+        .fields
+        .iter()
+        .filter_map(|field| Some((field.ident.as_ref().unwrap(), comp_vec_type(&field.ty)?)))
+        .filter(|(name, _)| !no_has_comp_fields.contains(name))
+        .collect();
+    let has_comp_fields: Vec<_> = comp_vec_fields
+        .iter()
+        .filter(|(_, comp)| {
+            let comp = quote!(#comp).to_string();
+            comp_vec_fields
+                .iter()
+                .filter(|(_, other)| quote!(#other).to_string() == comp)
+                .count()
+                == 1
+        })
+        .copied()
+        .collect();
+
     for borrow_name in borrow_names.iter() {
         //let field_name_caps = field.ident.as_ref().unwrap().to_string().to_pascal_case();
         //let struct_name = format_ident!("{name}No{field_name_caps}");
@@ -100,6 +151,10 @@ pub fn world_derive(input: TokenStream) -> TokenStream {
             .collect();
 
         let field_types = fields.iter().map(|field| &field.ty);
+        // the borrows hold mutable references, so they can't be serialized
+        let borrow_derives = derives
+            .iter()
+            .filter(|d| STD_DERIVES.iter().any(|t| *d == t));
 
         let field_names: Vec<_> = fields
             .iter()
@@ -109,11 +164,23 @@ pub fn world_derive(input: TokenStream) -> TokenStream {
         let ignored_field_names = fields_to_ignore.iter().map(|f| f.ident.as_ref().unwrap());
         let ignored_field_types = fields_to_ignore.iter().map(|f| &f.ty);
 
+        let borrow_query_impls = query_impls(
+            quote! { impl<'a> },
+            quote! { #borrow_name <'a> },
+            &format_ident!("__VecEcs{borrow_name}Split"),
+            &has_comp_fields
+                .iter()
+                .filter(|(name, _)| field_names.contains(name))
+                .copied()
+                .collect::<Vec<_>>(),
+            quote! { * },
+        );
+
         let borrow_name_snake = borrow_name.to_string().to_snek_case();
         let func_name = format_ident!("split_{borrow_name_snake}");
 
         let q = quote! {
-            #[derive(Debug)]
+            #[derive(Debug, #(#borrow_derives),*)]
             pub struct #borrow_name <'a> {
                 #handles_name: &'a mut #handle_ty,
                 #(
@@ -142,7 +209,12 @@ pub fn world_derive(input: TokenStream) -> TokenStream {
                 fn is_entity_already_freed(&self, handle: vec_ecs::EntityHandle) -> bool {
                     self. #handles_name .is_already_freed(handle)
                 }
+                fn handles(&self) -> &vec_ecs::EntityHandleCounter {
+                    self. #handles_name
+                }
             }
+
+            #borrow_query_impls
         };
         struct_defs.push(q);
     }
@@ -154,11 +226,175 @@ pub fn world_derive(input: TokenStream) -> TokenStream {
         .map(|field| field.ident.as_ref().unwrap())
         .collect();
 
+    let field_types_other_than_handles: Vec<_> = st
+        .fields
+        .iter()
+        .filter(|field| field.ident != handles_field.ident)
+        .map(|field| &field.ty)
+        .collect();
+
+    let delta_impl = delta.then(|| {
+        let delta_name = format_ident!("{name}Delta");
+        let delta_doc = format!("Difference between two states of a `{name}`, see `{name}::delta`");
+        // the deltas are always Clone, and not Hash
+        let mut delta_derives: Vec<_> = derives
+            .iter()
+            .filter_map(|derive| match derive.to_string().as_str() {
+                "PartialEq" | "Eq" => Some(quote! { #[derive(#derive)] }),
+                "Serialize" | "Deserialize" => Some(quote! { #[derive(vec_ecs::serde:: #derive)] }),
+                _ => None,
+            })
+            .collect();
+        if derives
+            .iter()
+            .any(|derive| SERDE_DERIVES.contains(&derive.to_string().as_str()))
+        {
+            delta_derives.push(quote! { #[serde(crate = "vec_ecs::serde")] });
+        }
+        quote! {
+            #[doc = #delta_doc]
+            #[derive(Clone)]
+            #(
+                #delta_derives
+            )*
+            pub struct #delta_name {
+                pub #handles_name: vec_ecs::HandlesDelta,
+                #(
+                    pub #field_names_other_than_handles: <#field_types_other_than_handles as vec_ecs::DeltaStorage>::Delta,
+                )*
+            }
+
+            impl #name {
+                /// The spawned and despawned entities and the inserted, removed
+                /// and changed components from `self` to `newer`
+                pub fn delta(&self, newer: &Self) -> #delta_name {
+                    #delta_name {
+                        #handles_name: self. #handles_name .delta(&newer. #handles_name),
+                        #(
+                            #field_names_other_than_handles: vec_ecs::DeltaStorage::delta(&self. #field_names_other_than_handles, &newer. #field_names_other_than_handles),
+                        )*
+                    }
+                }
+
+                /// Turns `self` into the newer state of the delta. Errors without changing
+                /// the world if the delta doesn't fit it, like a corrupted one or one
+                /// made from another state
+                pub fn apply_delta(&mut self, delta: #delta_name) -> ::core::result::Result<(), vec_ecs::DeltaError> {
+                    // the components are checked against the handles after the delta
+                    let mut handles = ::core::clone::Clone::clone(&self. #handles_name);
+                    handles.apply_delta(&delta. #handles_name)?;
+                    #(
+                        vec_ecs::DeltaStorage::check_delta(&self. #field_names_other_than_handles, &delta. #field_names_other_than_handles, &handles)?;
+                    )*
+                    self. #handles_name = handles;
+                    #(
+                        vec_ecs::DeltaStorage::apply_delta(&mut self. #field_names_other_than_handles, delta. #field_names_other_than_handles, &self. #handles_name)?;
+                    )*
+                    ::core::result::Result::Ok(())
+                }
+            }
+        }
+    });
+
+    let rollback_impl = rollback.then(|| {
+        let frame_name = format_ident!("{name}Frame");
+        let frame_doc = format!("Saved state of a `{name}`, see `vec_ecs::RollbackBuffer`");
+        quote! {
+            #[doc = #frame_doc]
+            #[derive(Clone)]
+            pub struct #frame_name {
+                #handles_name: #handle_ty,
+                #(
+                    #field_names_other_than_handles: ::std::sync::Arc<#field_types_other_than_handles>,
+                )*
+            }
+
+            impl vec_ecs::RollbackWorld for #name {
+                type Frame = #frame_name;
+
+                fn save_frame(&mut self, base: Option<&#frame_name>) -> #frame_name {
+                    #frame_name {
+                        #handles_name: self. #handles_name .clone(),
+                        #(
+                            #field_names_other_than_handles: vec_ecs::RollbackStorage::save_copy(&mut self. #field_names_other_than_handles, base.map(|base| &base. #field_names_other_than_handles)),
+                        )*
+                    }
+                }
+
+                fn restore_frame(&mut self, frame: &#frame_name, base: Option<&#frame_name>) {
+                    self. #handles_name .clone_from(&frame. #handles_name);
+                    #(
+                        vec_ecs::RollbackStorage::restore_copy(&mut self. #field_names_other_than_handles, &frame. #field_names_other_than_handles, base.map(|base| &base. #field_names_other_than_handles));
+                    )*
+                }
+            }
+        }
+    });
+
+    let world_query_impls = query_impls(
+        quote! { impl },
+        quote! { #name },
+        &format_ident!("__VecEcs{name}Split"),
+        &has_comp_fields,
+        quote! {},
+    );
+
+    let snapshot_impl = snapshot.then(|| {
+        let field_strs = field_names_other_than_handles
+            .iter()
+            .map(|name| name.to_string());
+        let schema = quote! {
+            [#(
+                (#field_strs, <#field_types_other_than_handles as vec_ecs::SnapshotStorage>::type_hash()),
+            )*]
+        };
+        quote! {
+            impl #name {
+                /// Writes a binary snapshot of the world: a header with the name and type hash
+                /// of every field, the entity handles, then every field
+                pub fn write_snapshot(&self, mut writer: impl ::std::io::Write) -> ::std::io::Result<()> {
+                    let writer = &mut writer;
+                    vec_ecs::write_snapshot_header(writer, &#schema)?;
+                    self. #handles_name .write_snapshot(writer)?;
+                    #(
+                        vec_ecs::SnapshotStorage::write_snapshot(&self. #field_names_other_than_handles, writer)?;
+                    )*
+                    ::std::io::Result::Ok(())
+                }
+
+                /// Reads a snapshot written by `write_snapshot`,
+                /// erroring if it was written by a world with other fields
+                pub fn read_snapshot(mut reader: impl ::std::io::Read) -> ::core::result::Result<Self, vec_ecs::SnapshotError> {
+                    let reader = &mut reader;
+                    vec_ecs::read_snapshot_header(reader, &#schema)?;
+                    let #handles_name = <#handle_ty>::read_snapshot(reader)?;
+                    #(
+                        let #field_names_other_than_handles = <#field_types_other_than_handles as vec_ecs::SnapshotStorage>::read_snapshot(reader)?;
+                    )*
+                    ::core::result::Result::Ok(Self {
+                        #handles_name,
+                        #(
+                            #field_names_other_than_handles,
+                        )*
+                    })
+                }
+            }
+        }
+    });
+
     let expanded = quote! {
         #(
             #struct_defs
         )*
 
+        #snapshot_impl
+
+        #delta_impl
+
+        #rollback_impl
+
+        #world_query_impls
+
         impl vec_ecs::WorldTrait for #name {
             fn delete_entity(&mut self, handle: vec_ecs::EntityHandle) {
                 if self. #handles_name .is_already_freed(handle) {
@@ -166,11 +402,11 @@ pub fn world_derive(input: TokenStream) -> TokenStream {
                 }
                 self. #handles_name .entity_deleted(handle);
                 #(
-                    self. #field_names_other_than_handles . remove(handle);
+                    vec_ecs::CompStorage::remove_entity(&mut self. #field_names_other_than_handles, handle);
                 )*
             }
             fn is_empty(&self) -> bool {
-                #(self. #field_names_other_than_handles . is_empty())&&*
+                #(vec_ecs::CompStorage::is_empty(&self. #field_names_other_than_handles))&&*
             }
         }
 
@@ -181,11 +417,101 @@ pub fn world_derive(input: TokenStream) -> TokenStream {
             fn is_entity_already_freed(&self, handle: vec_ecs::EntityHandle) -> bool {
                 self. #handles_name .is_already_freed(handle)
             }
+            fn handles(&self) -> &vec_ecs::EntityHandleCounter {
+                &self. #handles_name
+            }
         }
     };
     proc_macro::TokenStream::from(expanded)
 }
 
+/// `T` of a `CompVec<T>` field
+fn comp_vec_type(ty: &syn::Type) -> Option<&syn::Type> {
+    let syn::Type::Path(path) = ty else {
+        return None;
+    };
+    let segment = path.path.segments.last()?;
+    let syn::PathArguments::AngleBracketed(args) = &segment.arguments else {
+        return None;
+    };
+    match args.args.first()? {
+        syn::GenericArgument::Type(comp) if segment.ident == "CompVec" && args.args.len() == 1 => {
+            Some(comp)
+        }
+        _ => None,
+    }
+}
+
+/// `WorldQueryTrait` and `HasComp` impls of the world or of a borrow struct,
+/// `deref` being `*` for the borrow structs, whose fields are `&mut` storages
+fn query_impls(
+    impl_generics: proc_macro2::TokenStream,
+    ty: proc_macro2::TokenStream,
+    split_name: &Ident,
+    fields: &[(&Ident, &syn::Type)],
+    deref: proc_macro2::TokenStream,
+) -> proc_macro2::TokenStream {
+    let split_doc = format!(
+        "The `CompVec`s of a `{}` taken out by `HasComp::take_comps`",
+        ty.to_string().replace(' ', "")
+    );
+    let names: Vec<_> = fields.iter().map(|(name, _)| name).collect();
+    let comps: Vec<_> = fields.iter().map(|(_, comp)| comp).collect();
+    let twice = comps
+        .iter()
+        .map(|comp| format!("`{}` is in the query twice", quote!(#comp)));
+    // public as the `WorldQueryTrait::Split` of a public world, but hidden and
+    // prefixed to not clash with the user's types
+    quote! {
+        #[doc = #split_doc]
+        #[doc(hidden)]
+        pub struct #split_name <'vec_ecs_split> {
+            #(
+                #names: ::core::option::Option<&'vec_ecs_split mut vec_ecs::CompVec<#comps>>,
+            )*
+            _split: ::core::marker::PhantomData<&'vec_ecs_split mut ()>,
+        }
+
+        #impl_generics vec_ecs::WorldQueryTrait for #ty {
+            type Split<'vec_ecs_split> = #split_name <'vec_ecs_split> where Self: 'vec_ecs_split;
+
+            fn split_comps(&mut self) -> #split_name <'_> {
+                #split_name {
+                    #(
+                        #names: ::core::option::Option::Some(&mut #deref self. #names),
+                    )*
+                    _split: ::core::marker::PhantomData,
+                }
+            }
+        }
+
+        #(
+            #impl_generics vec_ecs::HasComp<#comps> for #ty {
+                fn comps(&self) -> &vec_ecs::CompVec<#comps> {
+                    &#deref self. #names
+                }
+
+                fn comps_mut(&mut self) -> &mut vec_ecs::CompVec<#comps> {
+                    &mut #deref self. #names
+                }
+
+                fn take_comps<'vec_ecs_split>(split: &mut #split_name <'vec_ecs_split>) -> &'vec_ecs_split mut vec_ecs::CompVec<#comps>
+                where
+                    Self: 'vec_ecs_split,
+                {
+                    split. #names .take().expect(#twice)
+                }
+            }
+        )*
+    }
+}
+
+/// Traits `#[world(derive(...))]` derives for the borrow and delta structs
+const STD_DERIVES: &[&str] = &["PartialEq", "Eq", "Hash"];
+/// Traits `#[world(derive(...))]` derives for the delta struct through the serde
+/// re-exported by vec_ecs, needing its `serde` feature
+const SERDE_DERIVES: &[&str] = &["Serialize", "Deserialize"];
+
 #[proc_macro_derive(Entity, attributes(entity))]
 pub fn entity_derive(input: TokenStream) -> TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
@@ -281,3 +607,181 @@ pub fn entity_derive(input: TokenStream) -> TokenStream {
     };
     proc_macro::TokenStream::from(expanded)
 }
+
+/// How a field of a `#[derive(Query)]` struct borrows its component
+struct QueryField<'a> {
+    name: &'a Ident,
+    mutable: bool,
+    optional: bool,
+}
+
+/// Parses `&'a T`, `&'a mut T`, `Option<&'a T>` and `Option<&'a mut T>`
+fn query_field(field: &syn::Field) -> syn::Result<QueryField<'_>> {
+    let name = field.ident.as_ref().unwrap();
+    let error = || {
+        syn::Error::new_spanned(
+            &field.ty,
+            "query fields are `&T`, `&mut T`, `Option<&T>` or `Option<&mut T>`",
+        )
+    };
+    let (ty, optional) = match &field.ty {
+        syn::Type::Path(path) => {
+            let segment = path.path.segments.last().ok_or_else(error)?;
+            let syn::PathArguments::AngleBracketed(args) = &segment.arguments else {
+                return Err(error());
+            };
+            match args.args.first() {
+                Some(syn::GenericArgument::Type(ty))
+                    if segment.ident == "Option" && args.args.len() == 1 =>
+                {
+                    (ty, true)
+                }
+                _ => return Err(error()),
+            }
+        }
+        ty => (ty, false),
+    };
+    match ty {
+        syn::Type::Reference(reference) => Ok(QueryField {
+            name,
+            mutable: reference.mutability.is_some(),
+            optional,
+        }),
+        _ => Err(error()),
+    }
+}
+
+#[proc_macro_derive(Query, attributes(query))]
+pub fn query_derive(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    let name = &input.ident;
+    let Some(lifetime) = input.generics.lifetimes().next().map(|l| &l.lifetime) else {
+        return syn::Error::new_spanned(name, "a query needs a lifetime, like `Movers<'a>`")
+            .to_compile_error()
+            .into();
+    };
+
+    let mut world_names = Vec::new();
+    let mut world_borrow_names = Vec::new();
+    let mut with = Vec::new();
+    let mut without = Vec::new();
+    for attr in input.attrs.iter() {
+        if attr.path().is_ident("query") {
+            let e = attr.parse_nested_meta(|meta| {
+                // all the attributes are `name = Ident`
+                let list = if meta.path.is_ident("world") {
+                    &mut world_names
+                } else if meta.path.is_ident("borrow") {
+                    &mut world_borrow_names
+                } else if meta.path.is_ident("with") {
+                    &mut with
+                } else if meta.path.is_ident("without") {
+                    &mut without
+                } else {
+                    return Err(meta.error("unsupported attribute"));
+                };
+                let value = meta.value()?;
+                let s: Ident = value.parse()?;
+                list.push(s);
+                Ok(())
+            });
+            if let Err(e) = e {
+                return e.to_compile_error().into();
+            }
+        }
+    }
+
+    let st = match input.data {
+        syn::Data::Struct(st) => st,
+        syn::Data::Enum(_) | syn::Data::Union(_) => {
+            return syn::Error::new_spanned(name, "Query can only be derived for structs")
+                .to_compile_error()
+                .into();
+        }
+    };
+    let mut fields = match st
+        .fields
+        .iter()
+        .map(query_field)
+        .collect::<syn::Result<Vec<_>>>()
+    {
+        Ok(fields) => fields,
+        Err(e) => return e.to_compile_error().into(),
+    };
+    // the first iterator of a `CompIter` decides which entities are iterated over
+    let Some(first) = fields.iter().position(|field| !field.optional) else {
+        return syn::Error::new_spanned(name, "a query needs at least one non-optional field")
+            .to_compile_error()
+            .into();
+    };
+    fields.swap(0, first);
+
+    let field_names: Vec<_> = fields.iter().map(|field| field.name).collect();
+    let iters = fields
+        .iter()
+        .map(|field| {
+            let name = field.name;
+            let iter = if field.mutable {
+                quote! { world. #name .iter_mut() }
+            } else {
+                quote! { world. #name .iter() }
+            };
+            if field.optional {
+                quote! { #iter .optional() }
+            } else {
+                iter
+            }
+        })
+        .collect::<Vec<_>>();
+    // the fields of the borrow structs are `&mut` storages
+    let query_body = |deref: proc_macro2::TokenStream| {
+        quote! {
+            vec_ecs::CompIter::from((
+                #(
+                    #iters,
+                )*
+            ))
+            #(
+                .with(&#deref world. #with)
+            )*
+            #(
+                .without(&#deref world. #without)
+            )*
+            .into_iter()
+            .map(|(id, #(#field_names,)*)| (id, Self { #(#field_names,)* }))
+        }
+    };
+
+    let world_body = query_body(quote! {});
+    let borrow_body = query_body(quote! { * });
+    let world_impls = world_names.iter().map(|world_name| {
+        quote! {
+            impl<#lifetime> vec_ecs::QueryFromWorldTrait<#lifetime, #world_name> for #name <#lifetime> {
+                fn query(world: &#lifetime mut #world_name) -> impl Iterator<Item = (vec_ecs::EntityHandle, Self)> {
+                    #world_body
+                }
+            }
+        }
+    });
+    let world_borrow_impls = world_borrow_names.iter().map(|world_borrow_name| {
+        quote! {
+            impl<#lifetime, 'vec_ecs_world: #lifetime> vec_ecs::QueryFromWorldTrait<#lifetime, #world_borrow_name <'vec_ecs_world>> for #name <#lifetime> {
+                fn query(world: &#lifetime mut #world_borrow_name <'vec_ecs_world>) -> impl Iterator<Item = (vec_ecs::EntityHandle, Self)> {
+                    #borrow_body
+                }
+            }
+        }
+    });
+
+    let expanded = quote! {
+        #(
+            #world_impls
+        )*
+
+        #(
+            #world_borrow_impls
+        )*
+    };
+    proc_macro::TokenStream::from(expanded)
+}